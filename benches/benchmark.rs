@@ -1,4 +1,4 @@
-use advent_of_code_2021::{get_data, get_day, get_days};
+use advent_of_code_2021::{get_data, get_day, get_days, get_variants};
 use criterion::{criterion_group, criterion_main, Criterion};
 
 fn criterion_benchmark(c: &mut Criterion) {
@@ -15,6 +15,20 @@ fn criterion_benchmark(c: &mut Criterion) {
                 day2(get_data(&path));
             })
         });
+
+        for (name, day1, day2) in get_variants(i) {
+            c.bench_function(&format!("day {} A ({})", i, name), |b| {
+                b.iter(|| {
+                    day1(get_data(&path));
+                })
+            });
+
+            c.bench_function(&format!("day {} B ({})", i, name), |b| {
+                b.iter(|| {
+                    day2(get_data(&path));
+                })
+            });
+        }
     }
 }
 
@@ -7,6 +7,21 @@ use advent_of_code_2021::*;
 
 fn main() {
     let args: Vec<String> = env::args().skip(1).collect();
+    // "--export ply" prints the day 19 scanner alignment for a 3D viewer
+    // instead, in the given format.
+    if args.first().map(String::as_str) == Some("--export") {
+        let format = args.get(1).map_or("json", String::as_str);
+        let (_, _, filename) = get_day(19);
+        let input = BufReader::new(fs::File::open(&filename).unwrap());
+        match export_scanners(input, format) {
+            Ok(export) => print!("{}", export),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
     let days = if args.len() == 1 && args[0] == "all" {
         (1..=25).map(|x| x.to_string()).collect()
     } else {
@@ -15,7 +30,21 @@ fn main() {
     for day in days {
         println!("Day {}:", day);
 
-        let (star_one, star_two, filename) = get_day(day.parse::<usize>().unwrap());
+        // "22:compressed" runs the named alternative implementation.
+        let (day, variant) = match day.split_once(':') {
+            Some((day, variant)) => (day, Some(variant)),
+            None => (day.as_str(), None),
+        };
+        let day = day.parse::<usize>().unwrap();
+        let (mut star_one, mut star_two, filename) = get_day(day);
+        if let Some(variant) = variant {
+            let (_, one, two) = get_variants(day)
+                .into_iter()
+                .find(|(name, _, _)| *name == variant)
+                .unwrap_or_else(|| panic!("Day {} has no {:?} variant", day, variant));
+            star_one = one;
+            star_two = two;
+        }
         let f = fs::File::open(&filename).unwrap();
         let input = BufReader::new(f);
         println!("Star One: {}", star_one(Box::new(input)));
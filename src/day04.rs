@@ -1,157 +1,200 @@
-use std::{io::BufRead, str::FromStr};
+use std::{collections::HashMap, io::BufRead, str::FromStr};
 
 #[derive(Debug, Clone)]
-struct Board(Vec<Vec<(usize, bool)>>);
+struct Board {
+    rows: usize,
+    cols: usize,
+    cells: Vec<usize>,
+    marked: Vec<bool>,
+    row_hits: Vec<usize>,
+    col_hits: Vec<usize>,
+    diag_hits: [usize; 2],
+}
 
 impl FromStr for Board {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let t = s
+        let rows: Vec<Vec<usize>> = s
             .lines()
+            .filter(|l| !l.trim().is_empty())
             .map(|l| {
                 l.split_ascii_whitespace()
-                    .map(|v| (v.parse::<usize>().unwrap(), false))
+                    .map(|v| v.parse::<usize>().map_err(|e| format!("{}: {}", v, e)))
                     .collect()
             })
-            .collect();
-        Ok(Board(t))
+            .collect::<Result<_, _>>()?;
+        let cols = rows.first().map(|r| r.len()).unwrap_or(0);
+        if cols == 0 || rows.iter().any(|r| r.len() != cols) {
+            return Err(format!("Board rows are not all the same width: {:?}", s));
+        }
+        Ok(Board::new(
+            rows.len(),
+            cols,
+            rows.into_iter().flatten().collect(),
+        ))
     }
 }
 
 impl Board {
-    fn mark(&mut self, num: usize) -> bool {
-        let mut changed = None;
-        for (i, row) in self.0.iter_mut().enumerate() {
-            for (j, x) in row.iter_mut().enumerate() {
-                if x.0 == num {
-                    x.1 = true;
-                    // loop through checking horizontal & vertical
-                    changed = Some((i, j));
-                }
-            }
+    fn new(rows: usize, cols: usize, cells: Vec<usize>) -> Self {
+        Board {
+            rows,
+            cols,
+            marked: vec![false; cells.len()],
+            cells,
+            row_hits: vec![0; rows],
+            col_hits: vec![0; cols],
+            diag_hits: [0; 2],
+        }
+    }
+
+    fn is_square(&self) -> bool {
+        self.rows == self.cols
+    }
+
+    // Marks the cell at `index` and returns whether it completed a line.
+    fn mark(&mut self, index: usize, diagonals: bool) -> bool {
+        if self.marked[index] {
+            return false;
         }
-        if let Some((i, j)) = changed {
-            if self.0[i].iter().all(|x| x.1) {
-                return true;
+        self.marked[index] = true;
+        let (i, j) = (index / self.cols, index % self.cols);
+        self.row_hits[i] += 1;
+        self.col_hits[j] += 1;
+        let mut won = self.row_hits[i] == self.cols || self.col_hits[j] == self.rows;
+        if diagonals && self.is_square() {
+            if i == j {
+                self.diag_hits[0] += 1;
+                won |= self.diag_hits[0] == self.rows;
             }
-            if self.0.iter().all(|row| row[j].1) {
-                return true;
+            if i + j == self.cols - 1 {
+                self.diag_hits[1] += 1;
+                won |= self.diag_hits[1] == self.rows;
             }
         }
-        false
+        won
     }
-}
 
-#[allow(dead_code)]
-fn print(board: &Board) {
-    for row in &board.0 {
-        println!("{:?}", row);
+    fn unmarked_sum(&self) -> usize {
+        self.cells
+            .iter()
+            .zip(&self.marked)
+            .filter(|(_value, marked)| !**marked)
+            .map(|(value, _marked)| value)
+            .sum()
     }
 }
 
-fn get_winning_board(mut boards: Vec<Board>, numbers: Vec<usize>) -> (Board, usize) {
-    for number in numbers {
-        for board in boards.iter_mut() {
-            if board.mark(number) {
-                return (board.clone(), number);
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Win {
+    board: usize,
+    draw: usize,
+    number: usize,
+    score: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Bingo {
+    boards: Vec<Board>,
+    // number -> (board, cell) for every cell holding that number
+    index: HashMap<usize, Vec<(usize, usize)>>,
+    diagonals: bool,
+}
+
+impl Bingo {
+    fn new(boards: Vec<Board>) -> Self {
+        let mut index: HashMap<usize, Vec<(usize, usize)>> = HashMap::new();
+        for (b, board) in boards.iter().enumerate() {
+            for (c, &value) in board.cells.iter().enumerate() {
+                index.entry(value).or_default().push((b, c));
             }
         }
+        Bingo {
+            boards,
+            index,
+            diagonals: false,
+        }
     }
-    unreachable!()
-}
 
-fn drain<T>(possible: &mut Vec<T>, should_remove: impl Fn(&mut T) -> bool) {
-    let mut i = 0;
-    while i < possible.len() {
-        if should_remove(&mut possible[i]) {
-            possible.remove(i);
-        } else {
-            i += 1;
-        }
+    #[allow(dead_code)]
+    fn with_diagonals(mut self, diagonals: bool) -> Self {
+        self.diagonals = diagonals;
+        self
     }
-}
 
-fn get_least_winning(mut boards: Vec<Board>, numbers: Vec<usize>) -> (Board, usize) {
-    for number in numbers {
-        if boards.len() > 1 {
-            drain(&mut boards, |board| board.mark(number));
-        } else if boards[0].mark(number) {
-            return (boards[0].clone(), number);
+    // Plays every number and returns the boards in the order they won.
+    fn play(mut self, numbers: &[usize]) -> Vec<Win> {
+        let mut won = vec![false; self.boards.len()];
+        let mut wins = Vec::new();
+        for (draw, &number) in numbers.iter().enumerate() {
+            if wins.len() == self.boards.len() {
+                break;
+            }
+            let Some(cells) = self.index.get(&number) else {
+                continue;
+            };
+            for &(b, c) in cells {
+                if won[b] {
+                    continue;
+                }
+                if self.boards[b].mark(c, self.diagonals) {
+                    won[b] = true;
+                    wins.push(Win {
+                        board: b,
+                        draw,
+                        number,
+                        score: number * self.boards[b].unmarked_sum(),
+                    });
+                }
+            }
         }
+        wins
     }
-
-    unreachable!()
 }
 
-pub fn star_one(mut input: impl BufRead) -> usize {
+fn parse_input(mut input: impl BufRead) -> (Vec<usize>, Vec<Board>) {
     let mut buf = String::new();
 
     let _res = input.read_to_string(&mut buf);
 
     let mut sections = buf.split("\n\n");
 
-    let t: Vec<usize> = sections
+    let numbers: Vec<usize> = sections
         .next()
         .unwrap()
+        .trim()
         .split(',')
         .map(|c| c.parse::<usize>().unwrap_or_else(|_| panic!("{}", c)))
         .collect();
 
     let boards: Vec<Board> = sections
+        .filter(|section| !section.trim().is_empty())
         .map(|section| section.parse::<Board>().unwrap())
         .collect();
 
-    let (winning_board, number) = get_winning_board(boards, t);
-
-    // dbg!(&winning_board);
-
-    number
-        * winning_board
-            .0
-            .iter()
-            .flatten()
-            .filter(|(_value, marked)| !marked)
-            .map(|(value, _marked)| value)
-            .sum::<usize>()
+    (numbers, boards)
 }
 
-pub fn star_two(mut input: impl BufRead) -> usize {
-    let mut buf = String::new();
-
-    let _res = input.read_to_string(&mut buf);
-
-    let mut sections = buf.split("\n\n");
+pub fn star_one(input: impl BufRead) -> usize {
+    let (numbers, boards) = parse_input(input);
 
-    let t: Vec<usize> = sections
-        .next()
-        .unwrap()
-        .split(',')
-        .map(|c| c.parse::<usize>().unwrap_or_else(|_| panic!("{}", c)))
-        .collect();
+    let wins = Bingo::new(boards).play(&numbers);
 
-    let boards: Vec<Board> = sections
-        .map(|section| section.parse::<Board>().unwrap())
-        .collect();
+    wins.first().expect("No board won").score
+}
 
-    let (winning_board, number) = get_least_winning(boards, t);
+pub fn star_two(input: impl BufRead) -> usize {
+    let (numbers, boards) = parse_input(input);
 
-    // print(&winning_board);
-    // dbg!(number);
+    let wins = Bingo::new(boards).play(&numbers);
 
-    number
-        * winning_board
-            .0
-            .iter()
-            .flatten()
-            .filter(|(_value, marked)| !marked)
-            .map(|(value, _marked)| value)
-            .sum::<usize>()
+    wins.last().expect("No board won").score
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{star_one, star_two};
+    use super::{parse_input, star_one, star_two, Bingo, Board, Win};
     use std::io::Cursor;
 
     const INPUT: &[u8; 292] =
@@ -184,4 +227,42 @@ mod tests {
     fn test_star_two() {
         assert_eq!(star_two(Cursor::new(INPUT)), 1924);
     }
+
+    #[test]
+    fn test_winning_order() {
+        let (numbers, boards) = parse_input(Cursor::new(INPUT));
+        let wins = Bingo::new(boards).play(&numbers);
+        assert_eq!(
+            wins.iter().map(|w| (w.board, w.draw)).collect::<Vec<_>>(),
+            vec![(2, 11), (0, 13), (1, 14)]
+        );
+        assert_eq!(
+            wins[2],
+            Win {
+                board: 1,
+                draw: 14,
+                number: 13,
+                score: 1924
+            }
+        );
+    }
+
+    #[test]
+    fn test_diagonals() {
+        let board: Board = "1 2 3\n4 5 6\n7 8 9".parse().unwrap();
+        let numbers = [1, 5, 9];
+        assert!(Bingo::new(vec![board.clone()]).play(&numbers).is_empty());
+        let wins = Bingo::new(vec![board]).with_diagonals(true).play(&numbers);
+        assert_eq!(wins[0].draw, 2);
+        assert_eq!(wins[0].score, 9 * (2 + 3 + 4 + 6 + 7 + 8));
+    }
+
+    #[test]
+    fn test_rectangular_board() {
+        let board: Board = "1 2\n3 4\n5 6".parse().unwrap();
+        let wins = Bingo::new(vec![board]).play(&[2, 4, 6]);
+        assert_eq!(wins[0].draw, 2);
+        assert_eq!(wins[0].score, 6 * (1 + 3 + 5));
+        assert!("1 2\n3".parse::<Board>().is_err());
+    }
 }
@@ -1,98 +1,247 @@
-use std::{collections::HashMap, io::BufRead};
+use std::{
+    collections::{HashMap, HashSet},
+    io::BufRead,
+};
 
-type Point = (usize, usize);
+type Point = (i64, i64);
 
-fn parse_input(input: impl BufRead) -> impl Iterator<Item = (Point, Point)> {
-    input.lines().map(|l| {
-        let l = l.unwrap();
-        let (s, e) = l.split_once("->").unwrap();
-        let start = {
-            let (x, y) = s.split_once(',').unwrap();
-            (
-                x.trim().parse::<usize>().unwrap(),
-                y.trim()
-                    .parse::<usize>()
-                    .unwrap_or_else(|_| panic!("{}", y)),
-            )
-        };
-        let end = {
-            let (x, y) = e.split_once(',').unwrap();
-            (
-                x.trim().parse::<usize>().unwrap(),
-                y.trim().parse::<usize>().unwrap(),
-            )
-        };
-        if start.0 <= end.0 {
-            (start, end)
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn cross(a: Point, b: Point) -> i128 {
+    a.0 as i128 * b.1 as i128 - a.1 as i128 * b.0 as i128
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Segment {
+    start: Point,
+    end: Point,
+}
+
+impl Segment {
+    fn new(start: Point, end: Point) -> Self {
+        Segment { start, end }
+    }
+
+    fn is_axis_aligned(&self) -> bool {
+        self.start.0 == self.end.0 || self.start.1 == self.end.1
+    }
+
+    fn delta(&self) -> Point {
+        (self.end.0 - self.start.0, self.end.1 - self.start.1)
+    }
+
+    // Smallest lattice step along the segment, or (0, 0) for a single point.
+    fn step(&self) -> Point {
+        let (dx, dy) = self.delta();
+        let g = gcd(dx, dy);
+        if g == 0 {
+            (0, 0)
         } else {
-            (end, start)
+            (dx / g, dy / g)
         }
-    })
+    }
+
+    // Every lattice point the segment passes through, from start to end.
+    #[allow(dead_code)]
+    fn points(&self) -> impl Iterator<Item = Point> {
+        let (dx, dy) = self.delta();
+        let (sx, sy) = self.step();
+        let start = self.start;
+        (0..=gcd(dx, dy)).map(move |i| (start.0 + i * sx, start.1 + i * sy))
+    }
+
+    // The lattice line the segment lies on: a canonical direction and the
+    // constant `b * x - a * y` which is shared by every point on it.
+    fn line(&self) -> (Point, i64) {
+        let (mut a, mut b) = self.step();
+        if a < 0 || (a == 0 && b < 0) {
+            a = -a;
+            b = -b;
+        }
+        if (a, b) == (0, 0) {
+            // Treat single points as vertical so they group with other segments.
+            (a, b) = (0, 1);
+        }
+        ((a, b), b * self.start.0 - a * self.start.1)
+    }
+
+    fn bounds(&self) -> (Point, Point) {
+        (
+            (self.start.0.min(self.end.0), self.start.1.min(self.end.1)),
+            (self.start.0.max(self.end.0), self.start.1.max(self.end.1)),
+        )
+    }
+
+    fn contains(&self, point: Point) -> bool {
+        let (min, max) = self.bounds();
+        let offset = (point.0 - self.start.0, point.1 - self.start.1);
+        cross(offset, self.delta()) == 0
+            && (min.0..=max.0).contains(&point.0)
+            && (min.1..=max.1).contains(&point.1)
+    }
+
+    // The single lattice point where two non-parallel segments cross, if any.
+    fn crossing(&self, other: &Segment) -> Option<Point> {
+        let r = self.delta();
+        let s = other.delta();
+        if r == (0, 0) {
+            return other.contains(self.start).then_some(self.start);
+        }
+        if s == (0, 0) {
+            return self.contains(other.start).then_some(other.start);
+        }
+        let denom = cross(r, s);
+        if denom == 0 {
+            return None;
+        }
+        let qp = (other.start.0 - self.start.0, other.start.1 - self.start.1);
+        let (t, u, denom) = if denom < 0 {
+            (-cross(qp, s), -cross(qp, r), -denom)
+        } else {
+            (cross(qp, s), cross(qp, r), denom)
+        };
+        if !(0..=denom).contains(&t) || !(0..=denom).contains(&u) {
+            return None;
+        }
+        let x = r.0 as i128 * t;
+        let y = r.1 as i128 * t;
+        if x % denom != 0 || y % denom != 0 {
+            return None;
+        }
+        Some((
+            self.start.0 + (x / denom) as i64,
+            self.start.1 + (y / denom) as i64,
+        ))
+    }
 }
 
-pub fn star_one(input: impl BufRead) -> usize {
-    let freq = parse_input(input)
-        .filter(|(start, end)| start.0 == end.0 || start.1 == end.1)
-        .fold(HashMap::new(), |mut freq, (start, end)| {
-            // Vertical
-            if start.0 == end.0 {
-                for i in start.1.min(end.1)..=start.1.max(end.1) {
-                    let key = (start.0, i);
-                    *freq.entry(key).or_insert(0) += 1;
-                }
-            }
-            // Horizontal
-            if start.1 == end.1 {
-                for i in start.0.min(end.0)..=start.0.max(end.0) {
-                    let key = (i, start.1);
-                    *freq.entry(key).or_insert(0) += 1;
+// Counts the lattice points covered by at least two segments without
+// rasterizing them. Collinear segments are merged as intervals along their
+// line and crossings between different lines are found pairwise.
+fn count_overlaps(segments: &[Segment]) -> usize {
+    let mut lines: HashMap<(Point, i64), Vec<(i64, i64)>> = HashMap::new();
+    for segment in segments {
+        let line = segment.line();
+        let (a, b) = line.0;
+        let key = |p: Point| p.0 * a + p.1 * b;
+        let (k1, k2) = (key(segment.start), key(segment.end));
+        lines
+            .entry(line)
+            .or_default()
+            .push((k1.min(k2), k1.max(k2)));
+    }
+
+    let mut count = 0;
+    // Per line, the key ranges covered at least twice.
+    let mut overlapping: HashMap<(Point, i64), Vec<(i64, i64)>> = HashMap::new();
+    for (line, intervals) in &lines {
+        if intervals.len() < 2 {
+            continue;
+        }
+        let ((a, b), _) = *line;
+        let step = a * a + b * b;
+        let mut events: Vec<(i64, i64)> = intervals
+            .iter()
+            .flat_map(|&(lo, hi)| [(lo, 1), (hi + step, -1)])
+            .collect();
+        events.sort_unstable();
+        let mut depth = 0;
+        let mut regions = Vec::new();
+        for window in events.windows(2) {
+            depth += window[0].1;
+            let (from, to) = (window[0].0, window[1].0);
+            if depth >= 2 && to > from {
+                count += ((to - from) / step) as usize;
+                match regions.last_mut() {
+                    Some((_, end)) if *end == from - step => *end = to - step,
+                    _ => regions.push((from, to - step)),
                 }
             }
-            freq
-        });
+        }
+        if !regions.is_empty() {
+            overlapping.insert(*line, regions);
+        }
+    }
 
-    freq.into_iter().filter(|(_key, value)| value > &1).count()
-}
+    let mut sorted: Vec<(Segment, (Point, Point))> =
+        segments.iter().map(|s| (*s, s.bounds())).collect();
+    sorted.sort_unstable_by_key(|(_, (min, _))| min.0);
 
-pub fn star_two(input: impl BufRead) -> usize {
-    let freq = parse_input(input).fold(HashMap::new(), |mut freq, (start, end)| {
-        // Vertical
-        if start.0 == end.0 {
-            for i in start.1.min(end.1)..=start.1.max(end.1) {
-                let key = (start.0, i);
-                *freq.entry(key).or_insert(0) += 1;
+    let mut crossings = HashSet::new();
+    for (i, (segment, (min, max))) in sorted.iter().enumerate() {
+        for (other, (other_min, other_max)) in &sorted[i + 1..] {
+            if other_min.0 > max.0 {
+                break;
             }
-        // Horizontal
-        } else if start.1 == end.1 {
-            for i in start.0.min(end.0)..=start.0.max(end.0) {
-                let key = (i, start.1);
-                *freq.entry(key).or_insert(0) += 1;
+            if other_min.1 > max.1 || other_max.1 < min.1 {
+                continue;
             }
-        // Bottom left to top right
-        } else if start.1 < end.1 {
-            for i in 0..=(end.0 - start.0) {
-                let key = (start.0 + i, start.1 + i);
-                *freq.entry(key).or_insert(0) += 1;
+            if let Some(point) = segment.crossing(other) {
+                crossings.insert(point);
             }
-        // Top left to bottom right
-        } else if start.1 > end.1 {
-            for i in 0..=(end.0 - start.0) {
-                let key = (start.0 + i, start.1 - i);
-                *freq.entry(key).or_insert(0) += 1;
-            }
-        } else {
-            panic!()
         }
-        freq
-    });
+    }
+
+    // A crossing may already have been counted by the overlapping regions of
+    // none, one or several of its lines, but it must only count once.
+    let times_counted = |point: Point| {
+        overlapping
+            .iter()
+            .filter(|(&((a, b), c), regions)| {
+                b * point.0 - a * point.1 == c && {
+                    let k = point.0 * a + point.1 * b;
+                    regions.iter().any(|&(lo, hi)| lo <= k && k <= hi)
+                }
+            })
+            .count() as isize
+    };
 
-    freq.into_iter().filter(|(_key, value)| value > &1).count()
+    crossings.into_iter().fold(count as isize, |total, point| {
+        total + 1 - times_counted(point)
+    }) as usize
+}
+
+fn parse_point(s: &str) -> Point {
+    let (x, y) = s.split_once(',').unwrap();
+    (
+        x.trim().parse::<i64>().unwrap(),
+        y.trim().parse::<i64>().unwrap_or_else(|_| panic!("{}", y)),
+    )
+}
+
+fn parse_input(input: impl BufRead) -> impl Iterator<Item = Segment> {
+    input.lines().map(|l| {
+        let l = l.unwrap();
+        let (s, e) = l.split_once("->").unwrap();
+        Segment::new(parse_point(s), parse_point(e))
+    })
+}
+
+pub fn star_one(input: impl BufRead) -> usize {
+    let segments: Vec<Segment> = parse_input(input)
+        .filter(|segment| segment.is_axis_aligned())
+        .collect();
+
+    count_overlaps(&segments)
+}
+
+pub fn star_two(input: impl BufRead) -> usize {
+    let segments: Vec<Segment> = parse_input(input).collect();
+
+    count_overlaps(&segments)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{star_one, star_two};
-    use std::io::Cursor;
+    use super::{count_overlaps, star_one, star_two, Segment};
+    use crate::Random;
+    use std::{collections::HashMap, io::Cursor};
 
     const INPUT: &[u8; 109] = b"0,9 -> 5,9
 8,0 -> 0,8
@@ -105,6 +254,14 @@ mod tests {
 0,0 -> 8,8
 5,5 -> 8,2";
 
+    fn rasterized(segments: &[Segment]) -> usize {
+        let mut freq = HashMap::new();
+        for point in segments.iter().flat_map(|s| s.points()) {
+            *freq.entry(point).or_insert(0) += 1;
+        }
+        freq.into_values().filter(|&v| v > 1).count()
+    }
+
     #[test]
     fn test_star_one() {
         assert_eq!(star_one(Cursor::new(INPUT)), 5);
@@ -114,4 +271,46 @@ mod tests {
     fn test_star_two() {
         assert_eq!(star_two(Cursor::new(INPUT)), 12);
     }
+
+    #[test]
+    fn test_points_with_slope() {
+        let segment = Segment::new((-3, 5), (3, 1));
+        assert_eq!(
+            segment.points().collect::<Vec<_>>(),
+            vec![(-3, 5), (0, 3), (3, 1)]
+        );
+    }
+
+    #[test]
+    fn test_matches_rasterized() {
+        let mut random = Random(17);
+        let mut next = || random.range(6) as i64;
+        for _ in 0..100 {
+            let segments: Vec<Segment> = (0..40)
+                .map(|_| Segment::new((next(), next()), (next(), next())))
+                .collect();
+            assert_eq!(count_overlaps(&segments), rasterized(&segments));
+        }
+    }
+
+    #[test]
+    fn test_crossing_overlaps() {
+        let segments = [
+            Segment::new((881, 872), (141, 132)),
+            Segment::new((368, 476), (515, 476)),
+            Segment::new((981, 972), (120, 111)),
+            Segment::new((773, 476), (138, 476)),
+        ];
+        assert_eq!(count_overlaps(&segments), rasterized(&segments));
+    }
+
+    #[test]
+    fn test_huge_coordinates() {
+        let segments = [
+            Segment::new((-1_000_000_000, 0), (1_000_000_000, 0)),
+            Segment::new((0, -1_000_000_000), (0, 1_000_000_000)),
+            Segment::new((-500_000_000, 0), (1_500_000_000, 0)),
+        ];
+        assert_eq!(count_overlaps(&segments), 1_500_000_001);
+    }
 }
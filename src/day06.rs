@@ -1,56 +1,104 @@
-use std::{collections::HashMap, io::BufRead};
+use std::io::BufRead;
 
-pub fn star_one(mut input: impl BufRead) -> usize {
-    let mut buf = String::new();
-    let _res = input.read_to_string(&mut buf);
-    let mut fish: Vec<u8> = buf.split(',').map(|x| x.parse::<u8>().unwrap()).collect();
-
-    for _ in 0..80 {
-        let mut new_fish = Vec::new();
-        for f in fish.iter_mut() {
-            if *f == 0 {
-                *f = 7;
-                new_fish.push(8);
-            }
-            *f -= 1;
+use num_bigint::BigUint;
+
+use crate::matrix::{power, Matrix};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Timers {
+    // Timer a fish goes back to after spawning.
+    reset: usize,
+    // Timer a newly spawned fish starts with.
+    spawn: usize,
+}
+
+impl Default for Timers {
+    fn default() -> Self {
+        Timers { reset: 6, spawn: 8 }
+    }
+}
+
+impl Timers {
+    fn buckets(&self) -> usize {
+        self.reset.max(self.spawn) + 1
+    }
+
+    // transition[to][from] is how many fish with timer `to` one fish with
+    // timer `from` turns into after a day.
+    fn transition(&self) -> Matrix {
+        let n = self.buckets();
+        let mut matrix = vec![vec![BigUint::default(); n]; n];
+        for from in 1..n {
+            matrix[from - 1][from] += 1u8;
+        }
+        matrix[self.reset][0] += 1u8;
+        matrix[self.spawn][0] += 1u8;
+        matrix
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Population {
+    timers: Timers,
+    counts: Vec<BigUint>,
+}
+
+impl Population {
+    fn new(fish: &[usize], timers: Timers) -> Self {
+        let mut counts = vec![BigUint::default(); timers.buckets()];
+        for &f in fish {
+            assert!(f < counts.len(), "Timer {} is out of range", f);
+            counts[f] += 1u8;
+        }
+        Population { timers, counts }
+    }
+
+    fn advance(&self, days: usize) -> Population {
+        let matrix = power(&self.timers.transition(), days);
+        let counts = matrix
+            .iter()
+            .map(|row| row.iter().zip(&self.counts).map(|(m, c)| m * c).sum())
+            .collect();
+        Population {
+            timers: self.timers,
+            counts,
         }
-        fish.extend(new_fish);
     }
-    fish.len()
+
+    fn total(&self) -> BigUint {
+        self.counts.iter().sum()
+    }
 }
 
-pub fn star_two(mut input: impl BufRead) -> usize {
+fn parse_input(mut input: impl BufRead) -> Vec<usize> {
     let mut buf = String::new();
     let _res = input.read_to_string(&mut buf);
-    let fish = buf
+    buf.trim()
         .split(',')
-        .map(|x| x.parse::<u8>().unwrap())
-        .collect::<Vec<u8>>();
+        .map(|x| x.parse::<usize>().unwrap())
+        .collect()
+}
 
-    let mut ages: HashMap<u8, u128> = HashMap::new();
+fn count_after(input: impl BufRead, days: usize) -> usize {
+    let fish = parse_input(input);
+    let total = Population::new(&fish, Timers::default())
+        .advance(days)
+        .total();
+    usize::try_from(total).expect("Population does not fit in a usize")
+}
 
-    for f in fish {
-        *ages.entry(f).or_insert(0) += 1;
-    }
+pub fn star_one(input: impl BufRead) -> usize {
+    count_after(input, 80)
+}
 
-    for _ in 0..256 {
-        let mut new_ages = HashMap::new();
-        for (age, amount) in ages {
-            if age == 0 {
-                *new_ages.entry(6).or_insert(0) += amount;
-                *new_ages.entry(8).or_insert(0) += amount;
-            } else {
-                *new_ages.entry(age - 1).or_insert(0) += amount;
-            }
-        }
-        ages = new_ages;
-    }
-    ages.into_values().sum::<u128>() as usize
+pub fn star_two(input: impl BufRead) -> usize {
+    count_after(input, 256)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{star_one, star_two};
+    use super::{star_one, star_two, Population, Timers};
+    use num_bigint::BigUint;
     use std::io::Cursor;
 
     const INPUT: &[u8; 9] = b"3,4,3,1,2";
@@ -64,4 +112,33 @@ mod tests {
     fn test_star_two() {
         assert_eq!(star_two(Cursor::new(INPUT)), 26984457539);
     }
+
+    #[test]
+    fn test_matches_simulation() {
+        let timers = Timers { reset: 2, spawn: 4 };
+        let mut fish = vec![0, 1, 4];
+        let population = Population::new(&fish, timers);
+        for day in 1..=30 {
+            let mut new_fish = Vec::new();
+            for f in fish.iter_mut() {
+                if *f == 0 {
+                    *f = timers.reset;
+                    new_fish.push(timers.spawn);
+                } else {
+                    *f -= 1;
+                }
+            }
+            fish.extend(new_fish);
+            assert_eq!(population.advance(day).total(), BigUint::from(fish.len()));
+        }
+    }
+
+    #[test]
+    fn test_large_day_count() {
+        let population = Population::new(&[3, 4, 3, 1, 2], Timers::default());
+        let total = population.advance(10_000).total();
+        assert!(total.bits() > 1000);
+        let split = population.advance(4_000).advance(6_000).total();
+        assert_eq!(total, split);
+    }
 }
@@ -1,60 +1,83 @@
 use std::io::BufRead;
 
-pub fn star_one(mut input: impl BufRead) -> usize {
-    let mut buf = String::new();
-    let _res = input.read_to_string(&mut buf);
-    let mut data = buf
-        .split(',')
-        .map(|x| x.parse::<usize>().unwrap())
-        .collect::<Vec<usize>>();
-    data.sort_unstable();
+// Fuel used to move a crab a given distance. Implementations must be convex
+// (non-decreasing step costs) for `align` to find the true optimum.
+trait Cost {
+    fn cost(&self, distance: usize) -> usize;
+}
 
-    let mid = data.len() / 2;
+impl<F: Fn(usize) -> usize> Cost for F {
+    fn cost(&self, distance: usize) -> usize {
+        self(distance)
+    }
+}
 
-    let median = data[mid];
+fn linear(distance: usize) -> usize {
+    distance
+}
 
-    data.iter().map(|&x| median.abs_diff(x)).sum()
+fn triangular(distance: usize) -> usize {
+    distance * (distance + 1) / 2
 }
 
-pub fn star_two(mut input: impl BufRead) -> usize {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Alignment {
+    position: usize,
+    fuel: usize,
+}
+
+fn total_fuel(positions: &[usize], cost: &impl Cost, target: usize) -> usize {
+    positions
+        .iter()
+        .map(|&x| cost.cost(x.abs_diff(target)))
+        .sum()
+}
+
+// The total fuel is a sum of convex functions and so is convex itself, which
+// means its forward differences never decrease. Binary searching for the
+// first position where moving right stops helping finds the minimum, even
+// when the cost has plateaus.
+fn align(positions: &[usize], cost: &impl Cost) -> Option<Alignment> {
+    let mut lo = *positions.iter().min()?;
+    let mut hi = *positions.iter().max()?;
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if total_fuel(positions, cost, mid + 1) >= total_fuel(positions, cost, mid) {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    Some(Alignment {
+        position: lo,
+        fuel: total_fuel(positions, cost, lo),
+    })
+}
+
+fn parse_input(mut input: impl BufRead) -> Vec<usize> {
     let mut buf = String::new();
     let _res = input.read_to_string(&mut buf);
-    let data = buf
+    buf.trim()
         .split(',')
         .map(|x| x.parse::<usize>().unwrap())
-        .collect::<Vec<usize>>();
+        .collect()
+}
 
-    let mean = (data.iter().sum::<usize>() as f64 / data.len() as f64).ceil() as usize;
+pub fn star_one(input: impl BufRead) -> usize {
+    let data = parse_input(input);
 
-    let mid: usize = data
-        .iter()
-        .map(|&x| {
-            let distance = x.abs_diff(mean);
-            (1..=distance).sum::<usize>()
-        })
-        .sum();
-    let mean_left = mean - 1;
-    let left = data
-        .iter()
-        .map(|&x| {
-            let distance = x.abs_diff(mean_left);
-            (1..=distance).sum::<usize>()
-        })
-        .sum();
-    let mean_right = mean - 1;
-    let right = data
-        .iter()
-        .map(|&x| {
-            let distance = x.abs_diff(mean_right);
-            (1..=distance).sum::<usize>()
-        })
-        .sum();
-    mid.min(left).min(right)
+    align(&data, &linear).unwrap().fuel
+}
+
+pub fn star_two(input: impl BufRead) -> usize {
+    let data = parse_input(input);
+
+    align(&data, &triangular).unwrap().fuel
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{star_one, star_two};
+    use super::{align, linear, star_one, star_two, total_fuel, triangular, Alignment};
     use std::io::Cursor;
 
     const INPUT: &[u8; 21] = b"16,1,2,0,4,2,7,1,2,14";
@@ -68,4 +91,34 @@ mod tests {
     fn test_star_two() {
         assert_eq!(star_two(Cursor::new(INPUT)), 168);
     }
+
+    #[test]
+    fn test_alignment_position() {
+        let data = [16, 1, 2, 0, 4, 2, 7, 1, 2, 14];
+        assert_eq!(
+            align(&data, &linear),
+            Some(Alignment {
+                position: 2,
+                fuel: 37
+            })
+        );
+        assert_eq!(
+            align(&data, &triangular),
+            Some(Alignment {
+                position: 5,
+                fuel: 168
+            })
+        );
+        assert_eq!(align(&[], &linear), None);
+    }
+
+    #[test]
+    fn test_matches_brute_force() {
+        let costs: [&dyn Fn(usize) -> usize; 3] = [&linear, &triangular, &|d| d * d * d];
+        let data = [0, 0, 0, 1, 50, 51, 900, 3, 3, 3, 3, 3];
+        for cost in costs {
+            let best = (0..=900).map(|p| total_fuel(&data, &cost, p)).min();
+            assert_eq!(align(&data, &cost).map(|a| a.fuel), best);
+        }
+    }
 }
@@ -1,147 +1,112 @@
-use std::{
-    collections::{BTreeSet, HashMap, HashSet},
-    io::BufRead,
-};
-
-#[allow(dead_code)]
-enum DisplayPosition {
-    Top,
-    TopLeft,
-    BottomLeft,
-    Bottom,
-    TopRight,
-    BottomRight,
-    Middle,
+use std::io::BufRead;
+
+// Segments (and wires) are stored as bitmasks, `a` being the lowest bit.
+fn to_mask(pattern: &str) -> Result<u32, SolveError> {
+    pattern.trim().chars().try_fold(0, |mask, c| match c {
+        'a'..='z' => Ok(mask | 1 << (c as u32 - 'a' as u32)),
+        _ => Err(SolveError::InvalidWire(c)),
+    })
 }
-/*
-0: 6 Done
-1: 2 Done
-2: 5 Done
-3: 5 Done
-4: 4 Done
-5: 5 Done
-6: 6 Done
-7: 3 Done
-8: 7 Done
-9: 6 Done
-*/
-
-// We dont need to know the letters corresponding to the position so just need to know the combinations.
-fn guess_inputs_v2(mut inputs: Vec<BTreeSet<char>>) -> HashMap<BTreeSet<char>, usize> {
-    let mut positions = HashMap::new();
-
-    let position_1 = inputs.iter().position(|x| x.len() == 2).unwrap();
-    let inputs_for_1 = inputs.remove(position_1);
-    positions.insert(inputs_for_1.clone(), 1);
-
-    let position_7 = inputs.iter().position(|x| x.len() == 3).unwrap();
-    let inputs_for_7 = inputs.remove(position_7);
-    positions.insert(inputs_for_7, 7);
-
-    let position_4 = inputs.iter().position(|x| x.len() == 4).unwrap();
-    let inputs_for_4 = inputs.remove(position_4);
-    positions.insert(inputs_for_4.clone(), 4);
-
-    let position_8 = inputs.iter().position(|x| x.len() == 7).unwrap();
-    let inputs_for_8 = inputs.remove(position_8);
-    positions.insert(inputs_for_8, 8);
-
-    let position_3 = inputs
-        .iter()
-        .position(|x| x.len() == 5 && x.is_superset(&inputs_for_1))
-        .unwrap();
-    let inputs_for_3 = inputs.remove(position_3);
-    positions.insert(inputs_for_3, 3);
-
-    let position_9 = inputs
-        .iter()
-        .position(|x| x.len() == 6 && x.is_superset(&inputs_for_4))
-        .unwrap();
-    let inputs_for_9 = inputs.remove(position_9);
-    positions.insert(inputs_for_9, 9);
-
-    let position_6 = inputs
-        .iter()
-        .position(|x| x.len() == 6 && !x.is_superset(&inputs_for_1))
-        .unwrap();
-    let inputs_for_6 = inputs.remove(position_6);
-    positions.insert(inputs_for_6.clone(), 6);
-
-    let position_0 = inputs
-        .iter()
-        .position(|x| x.len() == 6 && x.is_superset(&inputs_for_1))
-        .unwrap();
-    let inputs_for_0 = inputs.remove(position_0);
-    positions.insert(inputs_for_0, 0);
-
-    let position_5 = inputs
-        .iter()
-        .position(|x| x.len() == 5 && x.symmetric_difference(&inputs_for_6).count() == 1)
-        .unwrap();
-    let inputs_for_5 = inputs.remove(position_5);
-    positions.insert(inputs_for_5, 5);
-
-    assert_eq!(inputs.len(), 1);
-    let inputs_for_2 = inputs.remove(0);
-    positions.insert(inputs_for_2, 2);
-
-    positions
-}
-
-// First try, not complete
-#[allow(dead_code)]
-fn guess_inputs(inputs: Vec<HashSet<char>>) -> HashMap<char, DisplayPosition> {
-    let mut positions = HashMap::new();
-
-    // get Top by removing 1s from 7
-    let inputs_for_1 = inputs.iter().find(|x| x.len() == 2).unwrap();
-    let inputs_for_7 = inputs.iter().find(|x| x.len() == 3).unwrap();
-    let inputs_for_8 = inputs.iter().find(|x| x.len() == 7).unwrap();
-
-    let top = inputs_for_7.difference(inputs_for_1).next().unwrap();
 
-    assert!(!positions.contains_key(top));
-
-    positions.insert(*top, DisplayPosition::Top);
+#[derive(Debug, Clone)]
+struct Font {
+    segments: usize,
+    // The lit segments of each digit, indexed by digit value.
+    digits: Vec<u32>,
+}
 
-    let inputs_for_3 = inputs
-        .iter()
-        .find(|x| x.len() == 5 && !x.is_superset(inputs_for_1))
-        .unwrap();
-    let inputs_for_4 = inputs
-        .iter()
-        .find(|x| x.len() != 2 && !x.contains(top))
-        .unwrap();
+impl Font {
+    fn new(digits: &[&str]) -> Self {
+        let digits: Vec<u32> = digits.iter().map(|d| to_mask(d).unwrap()).collect();
+        let segments = 32 - digits.iter().fold(0, |acc, d| acc | d).leading_zeros() as usize;
+        Font { segments, digits }
+    }
 
-    let lefts = inputs_for_8
-        .difference(inputs_for_3)
-        .cloned()
-        .collect::<HashSet<_>>();
-    let bottom_left_list: Vec<_> = lefts.difference(inputs_for_4).collect();
+    fn seven_segment() -> Self {
+        Font::new(&[
+            "abcefg", "cf", "acdeg", "acdfg", "bcdf", "abdfg", "abdefg", "acf", "abcdefg", "abcdfg",
+        ])
+    }
 
-    assert_eq!(bottom_left_list.len(), 1);
-    assert!(!positions.contains_key(top));
-    positions.insert(*bottom_left_list[0], DisplayPosition::BottomLeft);
+    fn digit(&self, segments: u32) -> Option<usize> {
+        self.digits.iter().position(|&d| d == segments)
+    }
+}
 
-    let top_left_list: Vec<_> = lefts.intersection(inputs_for_4).collect();
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Wiring(Vec<usize>);
 
-    assert_eq!(top_left_list.len(), 1);
-    assert!(!positions.contains_key(top));
-    positions.insert(*top_left_list[0], DisplayPosition::TopLeft);
+impl Wiring {
+    fn apply(&self, wires: u32) -> u32 {
+        self.0
+            .iter()
+            .enumerate()
+            .filter(|(wire, _)| wires & 1 << wire != 0)
+            .fold(0, |mask, (_, segment)| mask | 1 << segment)
+    }
 
-    // Bottom
+    fn decode(&self, font: &Font, pattern: &str) -> Option<usize> {
+        font.digit(self.apply(to_mask(pattern).ok()?))
+    }
+}
 
-    let _top_bottom_left = inputs_for_3
-        .difference(inputs_for_4)
-        .cloned()
-        .collect::<HashSet<_>>();
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SolveError {
+    // No wiring maps every observed pattern onto a digit.
+    Inconsistent,
+    // More than one wiring fits; holds how many do.
+    Ambiguous(usize),
+    // A pattern names a wire other than a lowercase letter.
+    InvalidWire(char),
+}
 
-    let _top_bottom_left = inputs_for_3
-        .difference(inputs_for_4)
-        .cloned()
-        .collect::<HashSet<_>>();
+// Searches for wire -> segment permutations under which every observed
+// pattern lights up some digit of the font. Wires are assigned one at a time
+// and a partial assignment is abandoned as soon as a pattern can no longer
+// match a digit of its length.
+fn solve(font: &Font, patterns: &[u32]) -> Result<Wiring, SolveError> {
+    fn search(
+        font: &Font,
+        patterns: &[u32],
+        assigned: &mut Vec<usize>,
+        used: u32,
+        found: &mut Vec<Wiring>,
+    ) {
+        let wire = assigned.len();
+        let known = (1u32 << wire) - 1;
+        let wiring = Wiring(assigned.clone());
+        let consistent = patterns.iter().all(|&pattern| {
+            let mapped = wiring.apply(pattern & known);
+            font.digits
+                .iter()
+                .any(|&digit| digit.count_ones() == pattern.count_ones() && digit & used == mapped)
+        });
+        if !consistent {
+            return;
+        }
+        if wire == font.segments {
+            found.push(wiring);
+            return;
+        }
+        for segment in 0..font.segments {
+            if used & 1 << segment == 0 {
+                assigned.push(segment);
+                search(font, patterns, assigned, used | 1 << segment, found);
+                assigned.pop();
+            }
+        }
+    }
 
-    positions
+    if patterns.iter().any(|p| p >> font.segments != 0) {
+        return Err(SolveError::Inconsistent);
+    }
+    let mut found = Vec::new();
+    search(font, patterns, &mut Vec::new(), 0, &mut found);
+    match found.len() {
+        0 => Err(SolveError::Inconsistent),
+        1 => Ok(found.remove(0)),
+        n => Err(SolveError::Ambiguous(n)),
+    }
 }
 
 pub fn star_one(input: impl BufRead) -> usize {
@@ -164,19 +129,17 @@ pub fn star_two(input: impl BufRead) -> usize {
         .map(|line| {
             let line = line.unwrap();
             let (input, output) = line.split_once('|').unwrap();
-            let inputs = input
-                .trim()
-                .split(' ')
-                .map(|i| i.chars().collect::<BTreeSet<_>>())
-                .collect();
-
-            let mapping = guess_inputs_v2(inputs);
+            let font = Font::seven_segment();
+            let wiring = input
+                .split_ascii_whitespace()
+                .map(to_mask)
+                .collect::<Result<Vec<u32>, _>>()
+                .and_then(|patterns| solve(&font, &patterns))
+                .unwrap_or_else(|e| panic!("{:?}: {}", e, line));
 
             output
-                .trim()
-                .split(' ')
-                .map(|i| i.chars().collect::<BTreeSet<_>>())
-                .map(|display| mapping[&display])
+                .split_ascii_whitespace()
+                .map(|display| wiring.decode(&font, display).unwrap())
                 .fold(0, |acc, x| acc * 10 + x)
         })
         .sum()
@@ -184,7 +147,7 @@ pub fn star_two(input: impl BufRead) -> usize {
 
 #[cfg(test)]
 mod tests {
-    use super::{star_one, star_two};
+    use super::{solve, star_one, star_two, to_mask, Font, SolveError};
     use std::io::Cursor;
 
     const INPUT: &[u8] =
@@ -215,4 +178,36 @@ gcafb gcf dcaebfg ecagb gf abcdeg gaef cafbge fdbac fegbdc | fgae cfgab fg bagce
     fn test_star_two() {
         assert_eq!(star_two(Cursor::new(INPUT)), 61229);
     }
+
+    #[test]
+    fn test_solve_errors() {
+        let font = Font::seven_segment();
+        assert_eq!(
+            solve(&font, &[to_mask("ab").unwrap(), to_mask("cd").unwrap()]),
+            Err(SolveError::Inconsistent)
+        );
+        assert_eq!(
+            solve(&font, &[to_mask("abcdefg").unwrap()]),
+            Err(SolveError::Ambiguous(5040))
+        );
+        // Wires past the font's segments, and ones that are not letters.
+        assert_eq!(
+            solve(&font, &[to_mask("ah").unwrap()]),
+            Err(SolveError::Inconsistent)
+        );
+        assert_eq!(to_mask("aB"), Err(SolveError::InvalidWire('B')));
+        assert_eq!(to_mask("a\u{e9}"), Err(SolveError::InvalidWire('\u{e9}')));
+    }
+
+    #[test]
+    fn test_custom_font() {
+        // A four segment display where only the digit shapes tell wires apart.
+        let font = Font::new(&["abc", "a", "ab", "bcd"]);
+        let patterns = ["d", "cd", "bcd", "abc"].map(|p| to_mask(p).unwrap());
+        let wiring = solve(&font, &patterns).unwrap();
+        assert_eq!(wiring.decode(&font, "cd"), Some(2));
+        assert_eq!(wiring.decode(&font, "abc"), Some(3));
+        assert_eq!(wiring.decode(&font, "ac"), None);
+        assert_eq!(wiring.decode(&font, "c1"), None);
+    }
 }
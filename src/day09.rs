@@ -23,15 +23,7 @@ fn is_lowest(data: &[Vec<u32>], i: usize, j: usize) -> bool {
 }
 
 pub fn star_one(input: impl BufRead) -> usize {
-    let data: Vec<Vec<u32>> = input
-        .lines()
-        .map(|l| {
-            l.unwrap()
-                .chars()
-                .map(|c| c.to_digit(10).unwrap())
-                .collect()
-        })
-        .collect();
+    let data = parse_input(input);
     data.iter()
         .enumerate()
         .flat_map(|(i, row)| {
@@ -45,71 +37,153 @@ pub fn star_one(input: impl BufRead) -> usize {
         .sum()
 }
 
-fn get_size(visited: &mut [Vec<bool>], i: usize, j: usize) -> usize {
-    // Flood fill
+fn parse_input(input: impl BufRead) -> Vec<Vec<u32>> {
+    input
+        .lines()
+        .map(|l| {
+            l.unwrap()
+                .chars()
+                .map(|c| c.to_digit(10).unwrap())
+                .collect()
+        })
+        .collect()
+}
 
-    let mut total = 0;
-    let mut stack = vec![(i, j)];
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Connectivity {
+    Orthogonal,
+    #[allow(dead_code)]
+    Diagonal,
+}
+
+impl Connectivity {
+    // Neighbours that come later in row-major order, so that every adjacent
+    // pair is only looked at once.
+    fn forward(&self) -> &'static [(isize, isize)] {
+        match self {
+            Connectivity::Orthogonal => &[(0, 1), (1, 0)],
+            Connectivity::Diagonal => &[(0, 1), (1, -1), (1, 0), (1, 1)],
+        }
+    }
+}
+
+struct UnionFind {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(n: usize) -> Self {
+        UnionFind {
+            parent: (0..n).collect(),
+            size: vec![1; n],
+        }
+    }
+
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        let (big, small) = if self.size[a] >= self.size[b] {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        self.parent[small] = big;
+        self.size[big] += self.size[small];
+    }
+}
 
-    while let Some((i, j)) = stack.pop() {
-        if !visited[i][j] {
-            total += 1;
-            visited[i][j] = true;
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Basin {
+    // The lowest cell in the basin, the first in row-major order on ties.
+    low_point: (usize, usize),
+    cells: Vec<(usize, usize)>,
+}
 
-            let dirs = [(-1, 0), (0, -1), (1, 0), (0, 1)];
-            for (up, left) in dirs {
-                let pos_i = i as isize + up;
-                let pos_j = j as isize + left;
+impl Basin {
+    fn size(&self) -> usize {
+        self.cells.len()
+    }
+}
 
-                // Is the next one within the bounds and have we visited it.
-                if pos_i >= 0
-                    && pos_i < visited.len() as isize
+// Labels every basin in the map. Cells at or above `barrier` separate basins
+// and belong to none of them.
+fn basins(data: &[Vec<u32>], barrier: u32, connectivity: Connectivity) -> Vec<Basin> {
+    let height = data.len();
+    let width = data.first().map_or(0, |row| row.len());
+    let open = |i: usize, j: usize| data[i][j] < barrier;
+
+    let mut sets = UnionFind::new(width * height);
+    for i in 0..height {
+        for j in 0..width {
+            if !open(i, j) {
+                continue;
+            }
+            for &(di, dj) in connectivity.forward() {
+                let pos_i = i as isize + di;
+                let pos_j = j as isize + dj;
+                if pos_i < height as isize
                     && pos_j >= 0
-                    && pos_j < visited[i].len() as isize
-                    && !visited[pos_i as usize][pos_j as usize]
+                    && pos_j < width as isize
+                    && open(pos_i as usize, pos_j as usize)
                 {
-                    stack.push((pos_i as usize, pos_j as usize));
+                    sets.union(i * width + j, pos_i as usize * width + pos_j as usize);
                 }
             }
         }
     }
-    total
+
+    let mut labels = vec![usize::MAX; width * height];
+    let mut basins: Vec<Basin> = Vec::new();
+    for i in 0..height {
+        for j in 0..width {
+            if !open(i, j) {
+                continue;
+            }
+            let root = sets.find(i * width + j);
+            if labels[root] == usize::MAX {
+                labels[root] = basins.len();
+                basins.push(Basin {
+                    low_point: (i, j),
+                    cells: Vec::with_capacity(sets.size[root]),
+                });
+            }
+            let basin = &mut basins[labels[root]];
+            let (low_i, low_j) = basin.low_point;
+            if data[i][j] < data[low_i][low_j] {
+                basin.low_point = (i, j);
+            }
+            basin.cells.push((i, j));
+        }
+    }
+    basins
 }
 
 pub fn star_two(input: impl BufRead) -> usize {
-    let data: Vec<Vec<u32>> = input
-        .lines()
-        .map(|l| {
-            l.unwrap()
-                .chars()
-                .map(|c| c.to_digit(10).unwrap())
-                .collect()
-        })
-        .collect();
+    let data = parse_input(input);
 
-    let mut visited: Vec<Vec<_>> = data
+    let mut sizes: Vec<usize> = basins(&data, 9, Connectivity::Orthogonal)
         .iter()
-        // Create barriers if the value is 9 (highest value)
-        .map(|row| row.iter().map(|&x| x == 9).collect::<Vec<_>>())
+        .map(|basin| basin.size())
         .collect();
 
-    let mut total = Vec::new();
-
-    // Get next starting point
-    for i in 0..data.len() {
-        for j in 0..data[i].len() {
-            if !visited[i][j] {
-                total.push(get_size(&mut visited, i, j));
-            }
-        }
-    }
-    total.sort_unstable();
-    total.into_iter().rev().take(3).product()
+    sizes.sort_unstable();
+    sizes.into_iter().rev().take(3).product()
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{star_one, star_two};
+    use super::{basins, parse_input, star_one, star_two, Connectivity};
     use std::io::Cursor;
 
     const INPUT: &[u8] = b"2199943210
@@ -127,4 +201,33 @@ mod tests {
     fn test_star_two() {
         assert_eq!(star_two(Cursor::new(INPUT)), 1134);
     }
+
+    #[test]
+    fn test_basins() {
+        let data = parse_input(Cursor::new(INPUT));
+        let found = basins(&data, 9, Connectivity::Orthogonal);
+        assert_eq!(found.len(), 4);
+        assert_eq!(
+            found
+                .iter()
+                .map(|b| (b.low_point, b.size()))
+                .collect::<Vec<_>>(),
+            vec![((0, 1), 3), ((0, 9), 9), ((2, 2), 14), ((4, 6), 9)]
+        );
+        assert!(found[0].cells.contains(&(1, 0)));
+    }
+
+    #[test]
+    fn test_basin_options() {
+        let data = parse_input(Cursor::new(INPUT));
+        let diagonal = basins(&data, 9, Connectivity::Diagonal);
+        assert_eq!(diagonal.len(), 1);
+        assert_eq!(diagonal[0].size(), 50 - 15);
+
+        let low = basins(&data, 5, Connectivity::Orthogonal);
+        assert!(low
+            .iter()
+            .all(|b| b.cells.iter().all(|&(i, j)| data[i][j] < 5)));
+        assert_eq!(low.iter().map(|b| b.size()).collect::<Vec<_>>(), vec![3, 9]);
+    }
 }
@@ -1,103 +1,291 @@
 use std::io::BufRead;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 enum Status {
-    Corrupted(char),
-    Incomplete(Vec<char>),
+    Valid,
+    // A closer that does not match the innermost open bracket. `expected` is
+    // `None` when nothing was open.
+    Corrupted {
+        column: usize,
+        found: char,
+        expected: Option<char>,
+    },
+    // The closers needed, innermost first, to finish the line.
+    Incomplete(String),
+    // A character that is not part of any bracket pair.
+    Unknown {
+        column: usize,
+        found: char,
+    },
 }
 
-pub fn star_one(input: impl BufRead) -> usize {
-    input
-        .lines()
-        .map(|line| {
-            let line = line.unwrap();
-            let chars = line.chars();
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Insert { column: usize, c: char },
+    Delete { column: usize },
+    Replace { column: usize, c: char },
+}
 
-            let mut stack = Vec::new();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Open(usize),
+    Close(usize),
+}
 
-            for c in chars {
-                match (stack.last(), c) {
-                    (_, '(' | '[' | '{' | '<') => stack.push(c),
-                    (Some('('), ')') => {
-                        stack.pop();
-                    }
-                    (Some('['), ']') => {
-                        stack.pop();
-                    }
-                    (Some('{'), '}') => {
-                        stack.pop();
-                    }
-                    (Some('<'), '>') => {
-                        stack.pop();
+#[derive(Debug, Clone)]
+struct Checker {
+    pairs: Vec<(char, char)>,
+}
+
+impl Default for Checker {
+    fn default() -> Self {
+        Checker::new(&[('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')])
+    }
+}
+
+impl Checker {
+    fn new(pairs: &[(char, char)]) -> Self {
+        Checker {
+            pairs: pairs.to_vec(),
+        }
+    }
+
+    fn kind(&self, c: char) -> Option<Kind> {
+        self.pairs
+            .iter()
+            .enumerate()
+            .find_map(|(i, &(open, close))| {
+                if c == open {
+                    Some(Kind::Open(i))
+                } else if c == close {
+                    Some(Kind::Close(i))
+                } else {
+                    None
+                }
+            })
+    }
+
+    fn check(&self, line: &str) -> Status {
+        let mut stack = Vec::new();
+
+        for (column, c) in line.chars().enumerate() {
+            match self.kind(c) {
+                Some(Kind::Open(i)) => stack.push(i),
+                Some(Kind::Close(i)) if stack.last() == Some(&i) => {
+                    stack.pop();
+                }
+                Some(Kind::Close(_)) => {
+                    return Status::Corrupted {
+                        column,
+                        found: c,
+                        expected: stack.last().map(|&i| self.pairs[i].1),
                     }
-                    (_, _) => return Status::Corrupted(c),
                 }
+                None => return Status::Unknown { column, found: c },
+            }
+        }
+        if stack.is_empty() {
+            Status::Valid
+        } else {
+            Status::Incomplete(stack.iter().rev().map(|&i| self.pairs[i].1).collect())
+        }
+    }
+
+    // Finds a smallest set of single character edits after which the line is
+    // no longer corrupted (it may still be incomplete). Columns refer to the
+    // original line and the edits are in column order.
+    #[cfg(test)]
+    fn repair(&self, line: &str) -> Vec<Edit> {
+        let kinds: Vec<Option<Kind>> = line.chars().map(|c| self.kind(c)).collect();
+        let n = kinds.len();
+        let mut repair = Repair {
+            pairs: &self.pairs,
+            kinds: &kinds,
+            balanced: vec![vec![0; n + 1]; n + 1],
+            prefix: vec![0; n + 1],
+            edits: Vec::new(),
+        };
+
+        // balanced[i][j]: edits to make the characters i..j balanced, either
+        // by removing the first one or by pairing it with a later one.
+        for len in 1..=n {
+            for i in 0..=n - len {
+                let j = i + len;
+                let mut best = repair.balanced[i + 1][j] + 1;
+                for k in i + 1..j {
+                    best = best.min(repair.split_cost(i, k, j));
+                }
+                repair.balanced[i][j] = best;
             }
-            if !stack.is_empty() {
-                return Status::Incomplete(stack);
+        }
+
+        // prefix[i]: edits to make the characters from i onwards a valid,
+        // possibly incomplete, line. Openers may be left unclosed for free.
+        for i in (0..n).rev() {
+            let mut best = repair.prefix[i + 1] + 1;
+            if matches!(kinds[i], Some(Kind::Open(_))) {
+                best = best.min(repair.prefix[i + 1]);
             }
-            unreachable!()
-        })
-        .map(|s| match s {
-            Status::Corrupted(')') => 3,
-            Status::Corrupted(']') => 57,
-            Status::Corrupted('}') => 1197,
-            Status::Corrupted('>') => 25137,
-            Status::Corrupted(c) => panic!("{}", c),
-            Status::Incomplete(_) => 0,
-        })
-        .sum()
+            for k in i + 1..n {
+                best = best.min(repair.balanced[i][k + 1] + repair.prefix[k + 1]);
+            }
+            repair.prefix[i] = best;
+        }
+
+        repair.walk_prefix(0);
+        let mut edits = repair.edits;
+        edits.sort_by_key(|edit| match *edit {
+            Edit::Insert { column, .. } => (column, 0),
+            Edit::Delete { column } | Edit::Replace { column, .. } => (column, 1),
+        });
+        edits
+    }
 }
 
-pub fn star_two(input: impl BufRead) -> usize {
-    let mut scores: Vec<usize> = input
-        .lines()
-        .map(|line| {
-            let line = line.unwrap();
-            let chars = line.chars();
+// Tables for `Checker::repair` and the walk back through them which collects
+// one optimal set of edits.
+#[cfg(test)]
+struct Repair<'a> {
+    pairs: &'a [(char, char)],
+    kinds: &'a [Option<Kind>],
+    balanced: Vec<Vec<usize>>,
+    prefix: Vec<usize>,
+    edits: Vec<Edit>,
+}
 
-            let mut stack = Vec::new();
+#[cfg(test)]
+impl Repair<'_> {
+    // Edits needed to turn characters i and k into a matching pair, and the
+    // pair that is cheapest to use.
+    fn pair_cost(&self, i: usize, k: usize) -> (usize, usize) {
+        match (self.kinds[i], self.kinds[k]) {
+            (Some(Kind::Open(a)), Some(Kind::Close(b))) if a == b => (0, a),
+            (Some(Kind::Open(a)), _) => (1, a),
+            (_, Some(Kind::Close(b))) => (1, b),
+            _ => (2, 0),
+        }
+    }
 
-            for c in chars {
-                match (stack.last(), c) {
-                    (_, '(' | '[' | '{' | '<') => stack.push(c),
-                    (Some('('), ')') => {
-                        stack.pop();
-                    }
-                    (Some('['), ']') => {
-                        stack.pop();
-                    }
-                    (Some('{'), '}') => {
-                        stack.pop();
-                    }
-                    (Some('<'), '>') => {
-                        stack.pop();
-                    }
-                    (_, _) => return Status::Corrupted(c),
-                }
+    // Cost of balancing i..j by pairing i with k.
+    fn split_cost(&self, i: usize, k: usize, j: usize) -> usize {
+        self.pair_cost(i, k).0 + self.balanced[i + 1][k] + self.balanced[k + 1][j]
+    }
+
+    fn walk_prefix(&mut self, i: usize) {
+        let n = self.kinds.len();
+        if i == n {
+            return;
+        }
+        for k in i + 1..n {
+            if self.balanced[i][k + 1] + self.prefix[k + 1] == self.prefix[i] {
+                self.walk_balanced(i, k + 1);
+                return self.walk_prefix(k + 1);
             }
-            if !stack.is_empty() {
-                return Status::Incomplete(stack);
+        }
+        if !matches!(self.kinds[i], Some(Kind::Open(_))) || self.prefix[i + 1] != self.prefix[i] {
+            self.edits.push(Edit::Delete { column: i });
+        }
+        self.walk_prefix(i + 1)
+    }
+
+    fn walk_balanced(&mut self, i: usize, j: usize) {
+        if i >= j {
+            return;
+        }
+        for k in i + 1..j {
+            if self.split_cost(i, k, j) == self.balanced[i][j] {
+                let pair = self.pair_cost(i, k).1;
+                let (open, close) = self.pairs[pair];
+                if self.kinds[i] != Some(Kind::Open(pair)) {
+                    self.edits.push(Edit::Replace { column: i, c: open });
+                }
+                if self.kinds[k] != Some(Kind::Close(pair)) {
+                    self.edits.push(Edit::Replace {
+                        column: k,
+                        c: close,
+                    });
+                }
+                self.walk_balanced(i + 1, k);
+                return self.walk_balanced(k + 1, j);
             }
-            unreachable!()
-        })
-        .filter(|s| matches!(s, Status::Incomplete(_)))
-        .map(|s| match s {
-            Status::Corrupted(_c) => panic!(),
-            Status::Incomplete(stack) => {
-                let mut score = 0;
-                for c in stack.into_iter().rev() {
-                    score *= 5;
-                    score += match c {
-                        '(' => 1,
-                        '[' => 2,
-                        '{' => 3,
-                        '<' => 4,
-                        _ => panic!(),
-                    };
+        }
+        // Nothing to pair the character with, so give it a partner of its own.
+        match self.kinds[i] {
+            Some(Kind::Open(pair)) => self.edits.push(Edit::Insert {
+                column: i + 1,
+                c: self.pairs[pair].1,
+            }),
+            Some(Kind::Close(pair)) => self.edits.push(Edit::Insert {
+                column: i,
+                c: self.pairs[pair].0,
+            }),
+            None => self.edits.push(Edit::Delete { column: i }),
+        }
+        self.walk_balanced(i + 1, j)
+    }
+}
+
+// Applies edits produced by `Checker::repair` to the line they were made for.
+#[cfg(test)]
+fn apply_edits(line: &str, edits: &[Edit]) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let mut result = String::new();
+    let mut edits = edits.iter().peekable();
+    for column in 0..=chars.len() {
+        let mut keep = column < chars.len();
+        while let Some(&&edit) = edits.peek() {
+            match edit {
+                Edit::Insert { column: at, c } if at == column => result.push(c),
+                Edit::Delete { column: at } if at == column => keep = false,
+                Edit::Replace { column: at, c } if at == column => {
+                    result.push(c);
+                    keep = false;
                 }
-                score
+                _ => break,
             }
+            edits.next();
+        }
+        if keep {
+            result.push(chars[column]);
+        }
+    }
+    result
+}
+
+pub fn star_one(input: impl BufRead) -> usize {
+    let checker = Checker::default();
+    input
+        .lines()
+        .map(|line| match checker.check(&line.unwrap()) {
+            Status::Corrupted { found: ')', .. } => 3,
+            Status::Corrupted { found: ']', .. } => 57,
+            Status::Corrupted { found: '}', .. } => 1197,
+            Status::Corrupted { found: '>', .. } => 25137,
+            _ => 0,
+        })
+        .sum()
+}
+
+pub fn star_two(input: impl BufRead) -> usize {
+    let checker = Checker::default();
+    let mut scores: Vec<usize> = input
+        .lines()
+        .filter_map(|line| match checker.check(&line.unwrap()) {
+            Status::Incomplete(completion) => Some(completion),
+            _ => None,
+        })
+        .map(|completion| {
+            completion.chars().fold(0, |score, c| {
+                score * 5
+                    + match c {
+                        ')' => 1,
+                        ']' => 2,
+                        '}' => 3,
+                        '>' => 4,
+                        _ => panic!("{}", c),
+                    }
+            })
         })
         .collect();
 
@@ -107,7 +295,7 @@ pub fn star_two(input: impl BufRead) -> usize {
 
 #[cfg(test)]
 mod tests {
-    use super::{star_one, star_two};
+    use super::{apply_edits, star_one, star_two, Checker, Edit, Status};
     use std::io::Cursor;
 
     const INPUT: &[u8] = b"[({(<(())[]>[[{[]{<()<>>
@@ -130,4 +318,81 @@ mod tests {
     fn test_star_two() {
         assert_eq!(star_two(Cursor::new(INPUT)), 288957);
     }
+
+    #[test]
+    fn test_check() {
+        let checker = Checker::default();
+        assert_eq!(checker.check("([]{<>})"), Status::Valid);
+        assert_eq!(
+            checker.check("{([(<{}[<>[]}>{[]{[(<()>"),
+            Status::Corrupted {
+                column: 12,
+                found: '}',
+                expected: Some(']')
+            }
+        );
+        assert_eq!(
+            checker.check(")"),
+            Status::Corrupted {
+                column: 0,
+                found: ')',
+                expected: None
+            }
+        );
+        assert_eq!(
+            checker.check("[({(<(())[]>[[{[]{<()<>>"),
+            Status::Incomplete("}}]])})]".to_string())
+        );
+        assert_eq!(
+            checker.check("(a)"),
+            Status::Unknown {
+                column: 1,
+                found: 'a'
+            }
+        );
+    }
+
+    #[test]
+    fn test_custom_pairs() {
+        let checker = Checker::new(&[('a', 'b'), ('(', ')')]);
+        assert_eq!(checker.check("a(ab)b"), Status::Valid);
+        assert_eq!(checker.check("a(a"), Status::Incomplete("b)b".to_string()));
+        assert!(matches!(
+            checker.check("[]"),
+            Status::Unknown { column: 0, .. }
+        ));
+    }
+
+    #[test]
+    fn test_repair() {
+        let checker = Checker::default();
+        let line = "{([(<{}[<>[]}>{[]{[(<()>";
+        let edits = checker.repair(line);
+        assert_eq!(edits, vec![Edit::Replace { column: 12, c: ']' }]);
+        let repaired = apply_edits(line, &edits);
+        assert!(matches!(checker.check(&repaired), Status::Incomplete(_)));
+
+        for line in String::from_utf8_lossy(INPUT).lines() {
+            let edits = checker.repair(line);
+            let repaired = apply_edits(line, &edits);
+            match checker.check(line) {
+                Status::Corrupted { .. } => assert!(!edits.is_empty()),
+                _ => assert!(edits.is_empty()),
+            }
+            assert!(!matches!(
+                checker.check(&repaired),
+                Status::Corrupted { .. } | Status::Unknown { .. }
+            ));
+        }
+
+        let edits = checker.repair("(]>");
+        assert_eq!(edits, vec![Edit::Replace { column: 1, c: '<' }]);
+        assert_eq!(
+            checker.check(&apply_edits("(]>", &edits)),
+            Status::Incomplete(")".to_string())
+        );
+
+        let edits = checker.repair("(a]");
+        assert_eq!(edits, vec![Edit::Replace { column: 1, c: '[' }]);
+    }
 }
@@ -1,104 +1,183 @@
-use std::{collections::HashSet, io::BufRead};
+use std::{
+    collections::{HashMap, VecDeque},
+    io::BufRead,
+    str::FromStr,
+};
 
-fn get_next_9(data: &[Vec<usize>]) -> Option<(usize, usize)> {
-    for (i, row) in data.iter().enumerate() {
-        for (j, x) in row.iter().enumerate() {
-            if x > &9 {
-                return Some((i, j));
-            }
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Cavern {
+    width: usize,
+    height: usize,
+    energy: Vec<u8>,
+}
+
+impl FromStr for Cavern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rows: Vec<Vec<u8>> = s
+            .lines()
+            .map(|l| {
+                l.trim()
+                    .chars()
+                    .map(|c| {
+                        c.to_digit(10)
+                            .map(|d| d as u8)
+                            .ok_or_else(|| format!("Invalid energy level {:?}", c))
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?;
+        let width = rows.first().map_or(0, |r| r.len());
+        if rows.iter().any(|r| r.len() != width) {
+            return Err("Rows are not all the same width".to_string());
         }
+        Ok(Cavern {
+            width,
+            height: rows.len(),
+            energy: rows.into_iter().flatten().collect(),
+        })
     }
-    None
 }
 
-fn step(data: Vec<Vec<usize>>) -> Vec<Vec<usize>> {
-    let mut new_data = data
-        .iter()
-        .map(|row| row.iter().map(|x| x + 1).collect())
-        .collect::<Vec<Vec<usize>>>();
-    let dirs: [(isize, isize); 8] = [
-        (-1, -1),
-        (-1, 0),
-        (-1, 1),
-        (0, -1),
-        (0, 1),
-        (1, -1),
-        (1, 0),
-        (1, 1),
-    ];
-
-    let mut flashed = HashSet::new();
-
-    while let Some((i, j)) = get_next_9(&new_data) {
-        if new_data[i][j] > 9 {
-            new_data[i][j] = 0;
-            flashed.insert((i, j));
-            for (di, dj) in dirs {
-                let pos = (i as isize + di, j as isize + dj);
-                if pos.0 >= 0
-                    && pos.0 < new_data.len() as isize
-                    && pos.1 >= 0
-                    && (pos.1 as usize) < new_data[pos.0 as usize].len()
-                {
-                    let position = (pos.0 as usize, pos.1 as usize);
-                    if !flashed.contains(&position) {
-                        new_data[position.0][position.1] += 1;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cycle {
+    // The state after `offset` steps is the first to repeat...
+    offset: usize,
+    // ...and it comes back every `period` steps.
+    period: usize,
+}
+
+impl Cavern {
+    fn neighbours(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        let (i, j) = ((index / self.width) as isize, (index % self.width) as isize);
+        (-1..=1)
+            .flat_map(move |di| (-1..=1).map(move |dj| (i + di, j + dj)))
+            .filter(move |&(y, x)| {
+                (y, x) != (i, j)
+                    && y >= 0
+                    && x >= 0
+                    && y < self.height as isize
+                    && x < self.width as isize
+            })
+            .map(move |(y, x)| y as usize * self.width + x as usize)
+    }
+
+    // Advances one step and returns how many octopuses flashed. Each octopus
+    // is queued once, when its energy first goes past 9.
+    fn step(&mut self) -> usize {
+        let mut queue = VecDeque::new();
+        for (index, energy) in self.energy.iter_mut().enumerate() {
+            *energy += 1;
+            if *energy == 10 {
+                queue.push_back(index);
+            }
+        }
+
+        let mut flashes = 0;
+        while let Some(index) = queue.pop_front() {
+            flashes += 1;
+            let neighbours: Vec<usize> = self.neighbours(index).collect();
+            for n in neighbours {
+                if self.energy[n] < 10 {
+                    self.energy[n] += 1;
+                    if self.energy[n] == 10 {
+                        queue.push_back(n);
                     }
                 }
             }
         }
+
+        for energy in self.energy.iter_mut() {
+            if *energy > 9 {
+                *energy = 0;
+            }
+        }
+        flashes
+    }
+
+    // Flash counts for each of the next `steps` steps.
+    #[allow(dead_code)]
+    fn run(&mut self, steps: usize) -> Vec<usize> {
+        (0..steps).map(|_| self.step()).collect()
+    }
+
+    // The first step on which every octopus flashes, or `None` if the states
+    // start repeating before that happens.
+    fn first_synchronized(&self) -> Option<usize> {
+        let mut cavern = self.clone();
+        let mut seen = HashMap::new();
+        seen.insert(cavern.energy.clone(), 0);
+        for step in 1.. {
+            if cavern.step() == cavern.energy.len() {
+                return Some(step);
+            }
+            if seen.insert(cavern.energy.clone(), step).is_some() {
+                return None;
+            }
+        }
+        unreachable!()
     }
 
-    new_data
+    // Steps until a state repeats, returning the per-step flash counts seen
+    // along the way and the cycle, or `None` if no cycle shows up in `limit`
+    // steps.
+    fn find_cycle(&self, limit: usize) -> (Vec<usize>, Option<Cycle>) {
+        let mut cavern = self.clone();
+        let mut seen = HashMap::new();
+        seen.insert(cavern.energy.clone(), 0);
+        let mut flashes = Vec::new();
+        for step in 1..=limit {
+            flashes.push(cavern.step());
+            if let Some(offset) = seen.insert(cavern.energy.clone(), step) {
+                return (
+                    flashes,
+                    Some(Cycle {
+                        offset,
+                        period: step - offset,
+                    }),
+                );
+            }
+        }
+        (flashes, None)
+    }
+
+    // Total flashes over the next `steps` steps. Once the states cycle the
+    // remaining steps are worked out from the cycle rather than simulated.
+    fn flashes_after(&self, steps: usize) -> usize {
+        let (flashes, cycle) = self.find_cycle(steps);
+        let Some(Cycle { offset, period }) = cycle else {
+            return flashes.iter().sum();
+        };
+        let before: usize = flashes[..offset].iter().sum();
+        let repeating = &flashes[offset..offset + period];
+        let remaining = steps - offset;
+        before
+            + remaining / period * repeating.iter().sum::<usize>()
+            + repeating[..remaining % period].iter().sum::<usize>()
+    }
 }
 
-fn is_all_zeros(data: &[Vec<usize>]) -> bool {
-    data.iter().all(|row| row.iter().all(|&x| x == 0))
+fn parse_input(mut input: impl BufRead) -> Cavern {
+    let mut buf = String::new();
+    let _res = input.read_to_string(&mut buf);
+    buf.trim().parse().unwrap()
 }
 
 pub fn star_one(input: impl BufRead) -> usize {
-    let mut data: Vec<Vec<usize>> = input
-        .lines()
-        .map(|l| {
-            l.unwrap()
-                .chars()
-                .map(|c| c.to_digit(10).unwrap() as usize)
-                .collect()
-        })
-        .collect();
-    let mut flashes = 0;
-    for _ in 0..100 {
-        data = step(data);
-        flashes += data
-            .iter()
-            .flat_map(|row| row.iter().filter(|&&x| x == 0))
-            .count();
-    }
-    flashes
+    parse_input(input).flashes_after(100)
 }
 
 pub fn star_two(input: impl BufRead) -> usize {
-    let mut data: Vec<Vec<usize>> = input
-        .lines()
-        .map(|l| {
-            l.unwrap()
-                .chars()
-                .map(|c| c.to_digit(10).unwrap() as usize)
-                .collect()
-        })
-        .collect();
-    let mut i = 0;
-    while !is_all_zeros(&data) {
-        data = step(data);
-        i += 1;
-    }
-    i
+    parse_input(input)
+        .first_synchronized()
+        .expect("Octopuses never synchronize")
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{star_one, star_two, step};
-    use std::io::{BufRead, Cursor};
+    use super::{parse_input, star_one, star_two, Cavern, Cycle};
+    use std::io::Cursor;
 
     const INPUT: &[u8] = b"5483143223
 2745854711
@@ -118,18 +197,9 @@ mod tests {
 
     #[test]
     fn test_step_one() {
-        let input = Box::new(Cursor::new(INPUT)) as Box<dyn BufRead>;
-        let data = input
-            .lines()
-            .map(|l| {
-                l.unwrap()
-                    .chars()
-                    .map(|c| c.to_digit(10).unwrap() as usize)
-                    .collect()
-            })
-            .collect();
+        let mut data = parse_input(Cursor::new(INPUT));
 
-        let expected = b"6594254334
+        let expected: Cavern = "6594254334
 3856965822
 6375667284
 7252447257
@@ -138,23 +208,47 @@ mod tests {
 3287952832
 7993992245
 5957959665
-6394862637";
+6394862637"
+            .parse()
+            .unwrap();
 
-        let input = Box::new(Cursor::new(expected)) as Box<dyn BufRead>;
-        let expected: Vec<Vec<usize>> = input
-            .lines()
-            .map(|l| {
-                l.unwrap()
-                    .chars()
-                    .map(|c| c.to_digit(10).unwrap() as usize)
-                    .collect()
-            })
-            .collect();
-        assert_eq!(step(data), expected);
+        data.step();
+        assert_eq!(data, expected);
     }
 
     #[test]
     fn test_star_two() {
         assert_eq!(star_two(Cursor::new(INPUT)), 195);
     }
+
+    #[test]
+    fn test_cycle() {
+        let cavern = parse_input(Cursor::new(INPUT));
+        let (flashes, cycle) = cavern.find_cycle(1000);
+        let cycle = cycle.unwrap();
+        assert_eq!(cycle.period, 10);
+        assert!(cycle.offset <= 195);
+        assert_eq!(flashes[194], 100);
+
+        let simulated: usize = cavern.clone().run(1000).iter().sum();
+        assert_eq!(cavern.flashes_after(1000), simulated);
+        assert_eq!(cavern.flashes_after(10), 204);
+        assert!(cavern.flashes_after(1_000_000_000_000) > 0);
+    }
+
+    #[test]
+    fn test_never_synchronized() {
+        // Two neighbours half a cycle apart keep nudging each other along and
+        // take turns flashing every four or five steps.
+        let cavern: Cavern = "0\n5".parse().unwrap();
+        assert_eq!(cavern.first_synchronized(), None);
+        assert_eq!(
+            cavern.find_cycle(100).1,
+            Some(Cycle {
+                offset: 0,
+                period: 9
+            })
+        );
+        assert_eq!(cavern.flashes_after(25), 5);
+    }
 }
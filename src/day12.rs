@@ -1,173 +1,230 @@
-use std::{
-    collections::{HashMap, HashSet},
-    io::BufRead,
-};
-
-#[derive(Debug, Hash, PartialEq, Eq, Clone)]
-enum Node {
-    Start,
-    End,
-    Lower(usize),
-    Upper(usize),
-}
+use std::{collections::HashMap, hash::Hash, io::BufRead};
 
 fn is_all_lowercase(s: &str) -> bool {
     s.chars().all(|c| c.is_ascii_lowercase())
 }
 
-pub fn star_one(input: impl BufRead) -> usize {
-    let graph = input
-        .lines()
-        .map(|line| {
+#[derive(Debug, Clone)]
+struct CaveGraph {
+    names: Vec<String>,
+    small: Vec<bool>,
+    adjacency: Vec<Vec<usize>>,
+    start: usize,
+    end: usize,
+}
+
+impl CaveGraph {
+    fn parse(input: impl BufRead) -> Self {
+        let mut ids: HashMap<String, usize> = HashMap::new();
+        let mut graph = CaveGraph {
+            names: Vec::new(),
+            small: Vec::new(),
+            adjacency: Vec::new(),
+            start: 0,
+            end: 0,
+        };
+        let mut id = |graph: &mut CaveGraph, name: &str| {
+            *ids.entry(name.to_owned()).or_insert_with(|| {
+                graph.names.push(name.to_owned());
+                graph.small.push(is_all_lowercase(name));
+                graph.adjacency.push(Vec::new());
+                graph.names.len() - 1
+            })
+        };
+        let start = id(&mut graph, "start");
+        let end = id(&mut graph, "end");
+        for line in input.lines() {
             let line = line.unwrap();
-            let (node1, node2) = line.split_once('-').unwrap();
-            (node1.to_owned(), node2.to_owned())
+            let (node1, node2) = line.trim().split_once('-').unwrap();
+            let (n1, n2) = (id(&mut graph, node1), id(&mut graph, node2));
+            graph.adjacency[n1].push(n2);
+            graph.adjacency[n2].push(n1);
+        }
+        graph.start = start;
+        graph.end = end;
+        graph
+    }
+
+    fn id(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+
+    // Number of paths from start to end allowed by the policy. Sub-counts are
+    // memoized on (cave, policy state) so paths are never built. Two big caves
+    // next to each other would allow endless paths and are not supported.
+    fn count_paths<P: VisitPolicy>(&self, policy: &P) -> usize {
+        fn count<P: VisitPolicy>(
+            graph: &CaveGraph,
+            policy: &P,
+            node: usize,
+            state: P::State,
+            memo: &mut HashMap<(usize, P::State), usize>,
+        ) -> usize {
+            if node == graph.end {
+                return 1;
+            }
+            if let Some(&known) = memo.get(&(node, state.clone())) {
+                return known;
+            }
+            let total = graph.adjacency[node]
+                .iter()
+                .filter(|&&next| next != graph.start)
+                .filter_map(|&next| Some((next, policy.enter(graph, &state, next)?)))
+                .map(|(next, state)| count(graph, policy, next, state, memo))
+                .sum();
+            memo.insert((node, state), total);
+            total
+        }
+
+        let state = policy.initial(self);
+        count(self, policy, self.start, state, &mut HashMap::new())
+    }
+
+    // Lazily walks every path allowed by the policy, depth first.
+    #[allow(dead_code)]
+    fn paths<'a, P: VisitPolicy>(&'a self, policy: &'a P) -> impl Iterator<Item = Vec<&'a str>> {
+        let mut stack = vec![(vec![self.start], policy.initial(self))];
+        std::iter::from_fn(move || {
+            while let Some((path, state)) = stack.pop() {
+                let node = *path.last().unwrap();
+                if node == self.end {
+                    return Some(path.iter().map(|&n| self.names[n].as_str()).collect());
+                }
+                for &next in self.adjacency[node].iter().rev() {
+                    if next == self.start {
+                        continue;
+                    }
+                    if let Some(state) = policy.enter(self, &state, next) {
+                        let mut path = path.clone();
+                        path.push(next);
+                        stack.push((path, state));
+                    }
+                }
+            }
+            None
         })
-        .fold(
-            HashMap::new(),
-            |mut hm: HashMap<String, Vec<String>>, (n1, n2)| {
-                hm.entry(n1.clone()).or_default().push(n2.clone());
-                hm.entry(n2).or_default().push(n1);
-                hm
-            },
-        );
+    }
+}
 
-    let mut stack = vec![("start".to_owned(), Vec::new())];
-
-    let mut paths = HashSet::new();
-
-    while let Some((node, mut path)) = stack.pop() {
-        if node == "end" {
-            path.push("end".to_owned());
-            paths.insert(path);
-        } else if !is_all_lowercase(&node) || !path.contains(&node) {
-            path.push(node.clone());
-            stack.extend(
-                graph
-                    .get(&node)
-                    .unwrap()
-                    .iter()
-                    .map(|node| (node.to_owned(), path.clone())),
-            );
+// Decides which caves a path may enter, tracking whatever it needs in
+// `State`. The start cave is never re-entered regardless of the policy.
+trait VisitPolicy {
+    type State: Clone + Eq + Hash;
+
+    fn initial(&self, graph: &CaveGraph) -> Self::State;
+
+    fn enter(&self, graph: &CaveGraph, state: &Self::State, cave: usize) -> Option<Self::State>;
+}
+
+// Every small cave at most once.
+struct SmallOnce;
+
+impl VisitPolicy for SmallOnce {
+    type State = u64;
+
+    fn initial(&self, graph: &CaveGraph) -> u64 {
+        assert!(graph.names.len() <= 64, "Too many caves for a u64 mask");
+        0
+    }
+
+    fn enter(&self, graph: &CaveGraph, visited: &u64, cave: usize) -> Option<u64> {
+        if !graph.small[cave] {
+            Some(*visited)
+        } else if visited & 1 << cave == 0 {
+            Some(visited | 1 << cave)
+        } else {
+            None
         }
     }
-    paths.len()
 }
 
-pub fn star_two(input: impl BufRead) -> usize {
-    let mut keys = HashMap::new();
+// Small caves at most once, except for a single one which may be visited
+// twice.
+struct OneSmallTwice;
 
-    let graph = input
-        .lines()
-        .map(|line| {
-            let line = line.unwrap();
-            let (node1, node2) = line.split_once('-').unwrap();
-            (node1.to_owned(), node2.to_owned())
-        })
-        .map(|v| {
-            let n1 = match v.0.as_str() {
-                "start" => Node::Start,
-                "end" => Node::End,
-                s if is_all_lowercase(s) => {
-                    let len = keys.len() + 1;
-                    let key1 = *keys.entry(v.0).or_insert(len);
-                    Node::Lower(key1)
-                }
-                _ => {
-                    let len = keys.len() + 1;
-                    let key1 = *keys.entry(v.0).or_insert(len);
-                    Node::Upper(key1)
-                }
-            };
+impl VisitPolicy for OneSmallTwice {
+    type State = (u64, bool);
 
-            let n2 = match v.1.as_str() {
-                "start" => Node::Start,
-                "end" => Node::End,
-                s if is_all_lowercase(s) => {
-                    let len = keys.len() + 1;
+    fn initial(&self, graph: &CaveGraph) -> (u64, bool) {
+        (SmallOnce.initial(graph), false)
+    }
 
-                    let key2 = *keys.entry(v.1).or_insert(len);
-                    Node::Lower(key2)
-                }
-                _ => {
-                    let len = keys.len() + 1;
+    fn enter(
+        &self,
+        graph: &CaveGraph,
+        &(visited, used_twice): &(u64, bool),
+        cave: usize,
+    ) -> Option<(u64, bool)> {
+        if !graph.small[cave] {
+            Some((visited, used_twice))
+        } else if visited & 1 << cave == 0 {
+            Some((visited | 1 << cave, used_twice))
+        } else if !used_twice && cave != graph.end {
+            Some((visited, true))
+        } else {
+            None
+        }
+    }
+}
 
-                    let key2 = *keys.entry(v.1).or_insert(len);
-                    Node::Upper(key2)
-                }
-            };
-            (n1, n2)
-        })
-        .fold(
-            HashMap::new(),
-            |mut hm: HashMap<Node, Vec<Node>>, (n1, n2)| {
-                hm.entry(n1.clone()).or_default().push(n2.clone());
-                hm.entry(n2).or_default().push(n1);
-                hm
-            },
-        );
+// A visit limit per cave. Caves without an explicit limit fall back to one
+// visit for small caves and no limit for big ones.
+#[allow(dead_code)]
+struct PerCaveLimit {
+    limits: HashMap<usize, u8>,
+}
 
-    let small_caves = graph.keys().filter(|n| matches!(n, Node::Lower(_)));
-
-    let mut stack: Vec<(_, _, _, _)> = small_caves
-        .map(|cave| (Node::Start, Vec::new(), cave, HashMap::new()))
-        .collect();
-
-    let mut paths = HashSet::new();
-
-    while let Some((node, mut path, twice_small, mut visit_count)) = stack.pop() {
-        match node {
-            Node::Start => {
-                if path.is_empty() {
-                    path.push(node.clone());
-                    stack.extend(graph.get(&node).unwrap().iter().map(|node| {
-                        (
-                            node.to_owned(),
-                            path.clone(),
-                            twice_small,
-                            visit_count.clone(),
-                        )
-                    }));
-                }
-            }
-            Node::End => {
-                path.push(Node::End);
-                paths.insert(path);
-            }
-            Node::Lower(_) => {
-                let max_count = if twice_small == &node { 2 } else { 1 };
-                if visit_count.get(&node).unwrap_or(&0) < &max_count {
-                    path.push(node.clone());
-                    *visit_count.entry(node.clone()).or_insert(0) += 1;
-                    stack.extend(graph.get(&node).unwrap().iter().map(|node| {
-                        (
-                            node.to_owned(),
-                            path.clone(),
-                            twice_small,
-                            visit_count.clone(),
-                        )
-                    }));
-                }
-            }
-            Node::Upper(_) => {
-                path.push(node.clone());
-                stack.extend(graph.get(&node).unwrap().iter().map(|node| {
-                    (
-                        node.to_owned(),
-                        path.clone(),
-                        twice_small,
-                        visit_count.clone(),
-                    )
-                }));
+#[allow(dead_code)]
+impl PerCaveLimit {
+    fn new(graph: &CaveGraph, limits: &[(&str, u8)]) -> Self {
+        PerCaveLimit {
+            limits: limits
+                .iter()
+                .map(|(name, limit)| (graph.id(name).unwrap(), *limit))
+                .collect(),
+        }
+    }
+
+    fn limit(&self, graph: &CaveGraph, cave: usize) -> Option<u8> {
+        self.limits
+            .get(&cave)
+            .copied()
+            .or(graph.small[cave].then_some(1))
+    }
+}
+
+impl VisitPolicy for PerCaveLimit {
+    type State = Vec<u8>;
+
+    fn initial(&self, graph: &CaveGraph) -> Vec<u8> {
+        vec![0; graph.names.len()]
+    }
+
+    fn enter(&self, graph: &CaveGraph, visits: &Vec<u8>, cave: usize) -> Option<Vec<u8>> {
+        match self.limit(graph, cave) {
+            None => Some(visits.clone()),
+            Some(limit) if visits[cave] < limit => {
+                let mut visits = visits.clone();
+                visits[cave] += 1;
+                Some(visits)
             }
+            Some(_) => None,
         }
     }
-    paths.len()
+}
+
+pub fn star_one(input: impl BufRead) -> usize {
+    CaveGraph::parse(input).count_paths(&SmallOnce)
+}
+
+pub fn star_two(input: impl BufRead) -> usize {
+    CaveGraph::parse(input).count_paths(&OneSmallTwice)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{star_one, star_two};
+    use super::{star_one, star_two, CaveGraph, OneSmallTwice, PerCaveLimit, SmallOnce};
+    use std::collections::HashSet;
     use std::io::Cursor;
 
     const INPUT1: &[u8] = b"start-A
@@ -221,4 +278,40 @@ start-RW";
         assert_eq!(star_two(Cursor::new(INPUT2)), 103);
         assert_eq!(star_two(Cursor::new(INPUT3)), 3509);
     }
+
+    #[test]
+    fn test_paths_match_counts() {
+        for input in [INPUT1, INPUT2, INPUT3] {
+            let graph = CaveGraph::parse(Cursor::new(input));
+            let paths: HashSet<Vec<&str>> = graph.paths(&OneSmallTwice).collect();
+            assert_eq!(paths.len(), graph.count_paths(&OneSmallTwice));
+            assert_eq!(
+                graph.paths(&SmallOnce).count(),
+                graph.count_paths(&SmallOnce)
+            );
+        }
+
+        let graph = CaveGraph::parse(Cursor::new(INPUT1));
+        let paths: Vec<Vec<&str>> = graph.paths(&SmallOnce).collect();
+        assert!(paths.contains(&vec!["start", "A", "b", "A", "c", "A", "end"]));
+        assert!(paths
+            .iter()
+            .all(|p| p.first() == Some(&"start") && p.last() == Some(&"end")));
+    }
+
+    #[test]
+    fn test_per_cave_limits() {
+        let graph = CaveGraph::parse(Cursor::new(INPUT1));
+        let default = PerCaveLimit::new(&graph, &[]);
+        assert_eq!(graph.count_paths(&default), 10);
+
+        let b_twice = PerCaveLimit::new(&graph, &[("b", 2)]);
+        assert_eq!(graph.count_paths(&b_twice), 30);
+
+        let no_big = PerCaveLimit::new(&graph, &[("A", 0)]);
+        assert_eq!(
+            graph.paths(&no_big).collect::<Vec<_>>(),
+            vec![vec!["start", "b", "end"]]
+        );
+    }
 }
@@ -1,57 +1,210 @@
-use std::{collections::HashMap, io::BufRead};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::BufRead,
+    str::FromStr,
+};
 
 use itertools::Itertools;
+use num_bigint::BigUint;
+
+use crate::matrix::power;
+
+type Histogram = BTreeMap<char, BigUint>;
+
+// Elements are numbered by their position in `elements` and a pair (a, b) is
+// stored at index `a * elements.len() + b`. Pairs without a rule are left as
+// they are by each step.
+#[derive(Debug, Clone)]
+struct Polymer {
+    elements: Vec<char>,
+    template: Vec<usize>,
+    rules: HashMap<(usize, usize), usize>,
+}
+
+impl FromStr for Polymer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start_string, mapping) = s
+            .split_once("\n\n")
+            .ok_or("Missing blank line after the template")?;
+
+        let mut elements: Vec<char> = Vec::new();
+        let mut index = |c: char| match elements.iter().position(|&e| e == c) {
+            Some(i) => i,
+            None => {
+                elements.push(c);
+                elements.len() - 1
+            }
+        };
+
+        let template: Vec<usize> = start_string.trim().chars().map(&mut index).collect();
+        if template.is_empty() {
+            return Err("Empty template".to_string());
+        }
+
+        let mut rules = HashMap::new();
+        for line in mapping.lines().filter(|l| !l.trim().is_empty()) {
+            let (inputs, output) = line
+                .split_once(" -> ")
+                .ok_or_else(|| format!("Invalid rule {:?}", line))?;
+            let (Some((a, b)), Some(c)) = (
+                inputs.trim().chars().collect_tuple(),
+                output.trim().chars().exactly_one().ok(),
+            ) else {
+                return Err(format!("Invalid rule {:?}", line));
+            };
+            let pair = (index(a), index(b));
+            let output = index(c);
+            if rules.insert(pair, output).is_some_and(|old| old != output) {
+                return Err(format!("Conflicting rules for {}{}", a, b));
+            }
+        }
+
+        Ok(Polymer {
+            elements,
+            template,
+            rules,
+        })
+    }
+}
+
+impl Polymer {
+    fn pair(&self, a: usize, b: usize) -> usize {
+        a * self.elements.len() + b
+    }
+
+    fn initial_pairs(&self) -> Vec<BigUint> {
+        let mut pairs = vec![BigUint::default(); self.elements.len().pow(2)];
+        for (&a, &b) in self.template.iter().tuple_windows() {
+            pairs[self.pair(a, b)] += 1u8;
+        }
+        pairs
+    }
+
+    // Where each pair goes in one step: either the two pairs either side of
+    // the inserted element or, without a rule, the pair itself.
+    fn transitions(&self) -> Vec<Vec<usize>> {
+        let n = self.elements.len();
+        (0..n * n)
+            .map(|pair| {
+                let (a, b) = (pair / n, pair % n);
+                match self.rules.get(&(a, b)) {
+                    Some(&c) => vec![self.pair(a, c), self.pair(c, b)],
+                    None => vec![pair],
+                }
+            })
+            .collect()
+    }
+
+    fn step(&self, transitions: &[Vec<usize>], pairs: &[BigUint]) -> Vec<BigUint> {
+        let mut next = vec![BigUint::default(); pairs.len()];
+        for (pair, count) in pairs.iter().enumerate() {
+            for &to in &transitions[pair] {
+                next[to] += count;
+            }
+        }
+        next
+    }
+
+    // Every element but the last of the polymer starts exactly one pair.
+    fn histogram(&self, pairs: &[BigUint]) -> Histogram {
+        let n = self.elements.len();
+        let mut freq: Histogram = self
+            .elements
+            .iter()
+            .map(|&e| (e, BigUint::default()))
+            .collect();
+        for (pair, count) in pairs.iter().enumerate() {
+            *freq.get_mut(&self.elements[pair / n]).unwrap() += count;
+        }
+        *freq
+            .get_mut(&self.elements[*self.template.last().unwrap()])
+            .unwrap() += 1u8;
+        freq.retain(|_, count| *count != BigUint::default());
+        freq
+    }
+
+    // Element frequencies after 0, 1, 2, ... steps.
+    fn histograms(&self) -> impl Iterator<Item = Histogram> + '_ {
+        let transitions = self.transitions();
+        let mut pairs = self.initial_pairs();
+        std::iter::from_fn(move || {
+            let freq = self.histogram(&pairs);
+            pairs = self.step(&transitions, &pairs);
+            Some(freq)
+        })
+    }
+
+    // Element frequencies after `steps` steps, by raising the pair transition
+    // matrix to the power of `steps` instead of stepping through them all.
+    #[allow(dead_code)]
+    fn histogram_after(&self, steps: usize) -> Histogram {
+        let size = self.elements.len().pow(2);
+        let mut matrix = vec![vec![BigUint::default(); size]; size];
+        for (from, tos) in self.transitions().iter().enumerate() {
+            for &to in tos {
+                matrix[to][from] += 1u8;
+            }
+        }
+        let matrix = power(&matrix, steps);
+        let pairs = self.initial_pairs();
+        let counts: Vec<BigUint> = matrix
+            .iter()
+            .map(|row| row.iter().zip(&pairs).map(|(m, c)| m * c).sum())
+            .collect();
+        self.histogram(&counts)
+    }
+
+    // Checks that every pair of elements that can ever show up, starting from
+    // the template and following the rules, has a rule. Returns the missing
+    // pairs otherwise.
+    #[allow(dead_code)]
+    fn validate(&self) -> Result<(), Vec<(char, char)>> {
+        let mut alphabet: BTreeSet<usize> = self.template.iter().copied().collect();
+        loop {
+            let added: Vec<usize> = alphabet
+                .iter()
+                .cartesian_product(alphabet.iter())
+                .filter_map(|(&a, &b)| self.rules.get(&(a, b)).copied())
+                .filter(|c| !alphabet.contains(c))
+                .collect();
+            if added.is_empty() {
+                break;
+            }
+            alphabet.extend(added);
+        }
+
+        let missing: Vec<(char, char)> = alphabet
+            .iter()
+            .cartesian_product(alphabet.iter())
+            .filter(|&(&a, &b)| !self.rules.contains_key(&(a, b)))
+            .map(|(&a, &b)| (self.elements[a], self.elements[b]))
+            .sorted()
+            .collect();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(missing)
+        }
+    }
+}
 
 ///
-/// This stores the counts of each of the combinations adjacent pairs that are in the template.
-/// Loops through the polymer rules for each timestep and computes the new adjacent pairs HashMap
+/// Steps the pair counts of the template forward and returns the difference
+/// between the most and least common elements.
 fn star(mut input: impl BufRead, iterations: usize) -> usize {
     let mut buf = String::new();
 
     let _res = input.read_to_string(&mut buf);
 
-    let (start_string, mapping) = buf.split_once("\n\n").unwrap();
-
-    let rules = mapping.lines().fold(HashMap::new(), |mut hm, line| {
-        let (inputs, outputs) = line.split_once(" -> ").unwrap();
-
-        let inputs: Vec<_> = inputs.chars().collect();
-        let outputs: Vec<_> = outputs.chars().collect();
-
-        hm.insert((inputs[0], inputs[1]), outputs[0]);
-        hm
-    });
-
-    let mut polymer =
-        start_string
-            .chars()
-            .tuple_windows()
-            .fold(HashMap::new(), |mut hm, (c1, c2)| {
-                *hm.entry((c1, c2)).or_insert(0) += 1_u128;
-                hm
-            });
-
-    for _i in 0..iterations {
-        let mut new_polymer = HashMap::new();
-        for (input, output) in &rules {
-            if let Some(count) = polymer.get(input) {
-                *new_polymer.entry((input.0, *output)).or_insert(0) += count;
-                *new_polymer.entry((*output, input.1)).or_insert(0) += count;
-            }
-        }
-        polymer = new_polymer;
-    }
+    let polymer: Polymer = buf.parse().unwrap();
 
-    let freq = polymer.iter().fold(HashMap::new(), |mut hm, (k, v)| {
-        *hm.entry(k.0).or_insert(0) += v / 2;
-        *hm.entry(k.1).or_insert(0) += v / 2;
-        hm
-    });
+    let freq = polymer.histograms().nth(iterations).unwrap();
 
-    let max = freq.iter().max_by_key(|x| x.1).unwrap().1;
-    let min = freq.iter().min_by_key(|x| x.1).unwrap().1;
+    let (min, max) = freq.values().minmax().into_option().unwrap();
 
-    (max - min) as usize
+    usize::try_from(max - min).unwrap()
 }
 
 /// Completes in 185.08 us
@@ -66,7 +219,8 @@ pub fn star_two(input: impl BufRead) -> usize {
 
 #[cfg(test)]
 mod tests {
-    use super::{star_one, star_two};
+    use super::{star_one, star_two, Polymer};
+    use num_bigint::BigUint;
     use std::io::Cursor;
 
     const INPUT: &[u8] = b"NNCB
@@ -97,4 +251,43 @@ CN -> C";
     fn test_star_two() {
         assert_eq!(star_two(Cursor::new(INPUT)), 2188189693529);
     }
+
+    fn polymer() -> Polymer {
+        String::from_utf8_lossy(INPUT).parse().unwrap()
+    }
+
+    #[test]
+    fn test_histograms() {
+        let polymer = polymer();
+        let freq = polymer.histograms().nth(10).unwrap();
+        assert_eq!(freq[&'B'], BigUint::from(1749u32));
+        assert_eq!(freq[&'C'], BigUint::from(298u32));
+        assert_eq!(freq[&'H'], BigUint::from(161u32));
+        assert_eq!(freq[&'N'], BigUint::from(865u32));
+
+        // NCNBCHB after one step
+        let freq = polymer.histograms().nth(1).unwrap();
+        assert_eq!(freq[&'B'], BigUint::from(2u32));
+        assert_eq!(freq[&'N'], BigUint::from(2u32));
+    }
+
+    #[test]
+    fn test_histogram_after() {
+        let polymer = polymer();
+        assert_eq!(
+            polymer.histogram_after(40),
+            polymer.histograms().nth(40).unwrap()
+        );
+        let total: BigUint = polymer.histogram_after(200).values().sum();
+        assert_eq!(total, (BigUint::from(3u32) << 200u32) + 1u32);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(polymer().validate(), Ok(()));
+
+        let partial: Polymer = "NN\n\nNN -> C\nNC -> N".parse().unwrap();
+        assert_eq!(partial.validate(), Err(vec![('C', 'C'), ('C', 'N')]));
+        assert!("NN\n\nNN -> C\nNN -> B".parse::<Polymer>().is_err());
+    }
 }
@@ -1,219 +1,119 @@
-use std::{collections::BinaryHeap, io::BufRead};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashSet},
+    io::BufRead,
+};
 
-#[derive(Debug, PartialEq, Eq)]
-struct Node {
-    pos: (usize, usize),
-    score: usize,
-    cost: usize, // uses distance as well.
-}
+use itertools::Itertools;
 
-impl PartialOrd for Node {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
-    }
+// A map made of `multiplier` x `multiplier` copies of a tile, each copy's
+// risk raised by its distance in tiles from the top left one and wrapping
+// back round from 9 to 1. Risks are worked out when asked for rather than
+// stored.
+#[derive(Debug, Clone)]
+struct TiledMap {
+    tile: Vec<Vec<u8>>,
+    multiplier: usize,
 }
 
-impl Ord for Node {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        match self.cost.cmp(&other.cost) {
-            core::cmp::Ordering::Equal => {}
-            ord => return ord,
-        }
-        match self.score.cmp(&other.score) {
-            core::cmp::Ordering::Equal => {}
-            ord => return ord,
-        }
-        self.pos.cmp(&other.pos)
+impl TiledMap {
+    fn new(input: impl BufRead, multiplier: usize) -> Self {
+        let tile = input
+            .lines()
+            .map(|line| {
+                let line = line.unwrap();
+                line.chars()
+                    .map(|c| c.to_digit(10).unwrap() as u8)
+                    .collect()
+            })
+            .collect();
+        TiledMap { tile, multiplier }
     }
-}
-
-#[allow(dead_code)]
-pub fn star_one_old(input: impl BufRead) -> usize {
-    let map: Vec<Vec<_>> = input
-        .lines()
-        .map(|line| {
-            let line = line.unwrap();
-            line.chars()
-                .map(|c| c.to_digit(10).unwrap() as usize)
-                .collect()
-        })
-        .collect();
-
-    let mut stack = BinaryHeap::new();
-
-    stack.push(Node {
-        pos: (0, 0),
-        score: 0,
-        cost: 0,
-    });
-
-    let dirs: [(isize, isize); 2] = [
-        // (1, 1),
-        (1, 0),
-        (0, 1),
-        // (0, -1),
-        // (1, -1),
-        // (-1, 0),
-        // (-1, 1),
-        // (-1, -1),
-    ];
 
-    let mut min_total = usize::MAX;
-    let end_pos = (map.len() - 1, map[0].len() - 1);
-
-    while let Some(Node { pos, score, .. }) = stack.pop() {
-        if score < min_total {
-            if pos == end_pos {
-                println!("GOt a min {}", score);
-                min_total = min_total.min(score);
-            } else {
-                stack.extend(
-                    dirs.iter()
-                        .filter_map(|(di, dj)| {
-                            if pos.0 as isize + di >= 0 && pos.1 as isize + dj >= 0 {
-                                let new_pos = (
-                                    (pos.0 as isize + di) as usize,
-                                    (pos.1 as isize + dj) as usize,
-                                );
-                                if new_pos.0 < map.len() && new_pos.1 < map[new_pos.0].len() {
-                                    return Some(new_pos);
-                                }
-                            }
-                            None
-                        })
-                        .map(|new_pos| {
-                            let delta_x = end_pos.0 - new_pos.0;
-                            let delta_y = end_pos.1 - new_pos.1;
-                            let distance =
-                                f64::sqrt((delta_x.pow(2) + delta_y.pow(2)) as f64) as usize + 1;
-                            Node {
-                                pos: new_pos,
-                                score: score + map[pos.0][pos.1],
-                                cost: score + map[pos.0][pos.1] + distance,
-                            }
-                        }),
-                );
-            }
-        }
+    fn height(&self) -> usize {
+        self.tile.len() * self.multiplier
     }
-    min_total
-}
 
-fn get(costs: &[Vec<Option<usize>>], i: isize, j: isize) -> Option<usize> {
-    if i < 0 || j < 0 {
-        return None;
+    fn width(&self) -> usize {
+        self.tile[0].len() * self.multiplier
     }
-    costs
-        .get(i as usize)
-        .and_then(|row| row.get(j as usize).cloned())
-        .flatten()
-}
-
-fn get_min_costs(costs: &[Vec<Option<usize>>], i: isize, j: isize) -> Option<usize> {
-    let dirs: [(isize, isize); 4] = [
-        // (1, 1),
-        (1, 0),
-        (0, 1),
-        (0, -1),
-        // (1, -1),
-        (-1, 0),
-        // (-1, 1),
-        // (-1, -1),
-    ];
-    dirs.iter()
-        .filter_map(|(di, dj)| get(costs, i + di, j + dj))
-        .min()
-}
 
-pub fn star_one(input: impl BufRead) -> usize {
-    let map: Vec<Vec<_>> = input
-        .lines()
-        .map(|line| {
-            let line = line.unwrap();
-            line.chars()
-                .map(|c| c.to_digit(10).unwrap() as u8)
-                .collect()
-        })
-        .collect();
+    fn risk(&self, i: usize, j: usize) -> u8 {
+        let (height, width) = (self.tile.len(), self.tile[0].len());
+        let inc = i / height + j / width;
+        let risk = self.tile[i % height][j % width] as usize + inc;
+        ((risk - 1) % 9 + 1) as u8
+    }
 
-    let mut costs: Vec<Vec<Option<usize>>> = (0..map.len())
-        .map(|x| (0..map[x].len()).map(|_| None).collect())
-        .collect();
+    // Dijkstra from the top left to the bottom right corner. Returns the total
+    // risk and the positions along the way, both ends included.
+    fn lowest_risk_path(&self) -> (usize, Vec<(usize, usize)>) {
+        let (height, width) = (self.height(), self.width());
+        let end = (height - 1, width - 1);
+        let mut best = vec![usize::MAX; height * width];
+        let mut previous = vec![None; height * width];
+        let mut queue = BinaryHeap::new();
 
-    costs[0][0] = Some(0);
+        best[0] = 0;
+        queue.push(Reverse((0, (0, 0))));
 
-    for i in 0..costs.len() {
-        for j in 0..costs[i].len() {
-            if let Some(v) = get_min_costs(&costs, i as isize, j as isize) {
-                costs[i][j] = Some(v + map[i][j] as usize);
+        while let Some(Reverse((risk, pos))) = queue.pop() {
+            if pos == end {
+                break;
+            }
+            if risk > best[pos.0 * width + pos.1] {
+                continue;
+            }
+            let dirs: [(isize, isize); 4] = [(1, 0), (0, 1), (0, -1), (-1, 0)];
+            for (di, dj) in dirs {
+                let (i, j) = (pos.0 as isize + di, pos.1 as isize + dj);
+                if i < 0 || j < 0 || i as usize >= height || j as usize >= width {
+                    continue;
+                }
+                let next = (i as usize, j as usize);
+                let next_risk = risk + self.risk(next.0, next.1) as usize;
+                if next_risk < best[next.0 * width + next.1] {
+                    best[next.0 * width + next.1] = next_risk;
+                    previous[next.0 * width + next.1] = Some(pos);
+                    queue.push(Reverse((next_risk, next)));
+                }
             }
         }
-    }
-
-    costs.last().unwrap().last().unwrap().unwrap()
-}
-
-fn map_create(input: impl BufRead) -> Vec<Vec<u8>> {
-    let template: Vec<Vec<_>> = input
-        .lines()
-        .map(|line| {
-            let line = line.unwrap();
-            line.chars()
-                .map(|c| c.to_digit(10).unwrap() as u8)
-                .collect()
-        })
-        .collect();
 
-    let height = template.len();
-    let width = template[0].len();
+        let mut path = vec![end];
+        while let Some(pos) = previous[path.last().unwrap().0 * width + path.last().unwrap().1] {
+            path.push(pos);
+        }
+        path.reverse();
+        (best[end.0 * width + end.1], path)
+    }
 
-    (0..height * 5)
-        .map(|i| {
-            (0..width * 5)
-                .map(|j| {
-                    if i < height && j < width {
-                        template[i][j]
-                    } else {
-                        let inc = (i / height) + (j / width);
-                        let new_num = inc + template[i % height][j % width] as usize;
-                        if new_num > 9 {
-                            (new_num % 9) as u8
+    // The map with every cell off the path blanked out.
+    #[allow(dead_code)]
+    fn render(&self, path: &[(usize, usize)]) -> String {
+        let on_path: HashSet<_> = path.iter().collect();
+        (0..self.height())
+            .map(|i| {
+                (0..self.width())
+                    .map(|j| {
+                        if on_path.contains(&(i, j)) {
+                            char::from(b'0' + self.risk(i, j))
                         } else {
-                            new_num as u8
+                            '.'
                         }
-                    }
-                })
-                .collect()
-        })
-        .collect()
+                    })
+                    .collect::<String>()
+            })
+            .join("\n")
+    }
 }
 
-pub fn star_two(input: impl BufRead) -> usize {
-    let map = map_create(input);
-    let mut costs: Vec<Vec<Option<usize>>> = (0..map.len())
-        .map(|x| (0..map[x].len()).map(|_| None).collect())
-        .collect();
-
-    costs[0][0] = Some(0);
-
-    let mut changed = true;
-
-    while changed {
-        changed = false;
-        for i in 0..costs.len() {
-            for j in 0..costs[i].len() {
-                if let Some(v) = get_min_costs(&costs, i as isize, j as isize) {
-                    let new_value = v + map[i][j] as usize;
-                    if costs[i][j].map(|cost| cost > new_value).unwrap_or(true) {
-                        costs[i][j] = Some(new_value);
-                        changed = true;
-                    }
-                }
-            }
-        }
-    }
+pub fn star_one(input: impl BufRead) -> usize {
+    TiledMap::new(input, 1).lowest_risk_path().0
+}
 
-    costs.last().unwrap().last().unwrap().unwrap()
+pub fn star_two(input: impl BufRead) -> usize {
+    TiledMap::new(input, 5).lowest_risk_path().0
 }
 
 #[cfg(test)]
@@ -297,7 +197,10 @@ mod tests {
                     .collect()
             })
             .collect();
-        let p = map_create(Cursor::new(INPUT));
+        let map = TiledMap::new(Cursor::new(INPUT), 5);
+        let p: Vec<Vec<u8>> = (0..map.height())
+            .map(|i| (0..map.width()).map(|j| map.risk(i, j)).collect())
+            .collect();
 
         assert_eq!(p, expected);
     }
@@ -306,4 +209,52 @@ mod tests {
     fn test_star_two() {
         assert_eq!(star_two(Cursor::new(INPUT)), 315);
     }
+
+    #[test]
+    fn test_path() {
+        let map = TiledMap::new(Cursor::new(INPUT), 1);
+        let (risk, path) = map.lowest_risk_path();
+        assert_eq!(path.first(), Some(&(0, 0)));
+        assert_eq!(path.last(), Some(&(9, 9)));
+        assert_eq!(
+            path.iter()
+                .skip(1)
+                .map(|&(i, j)| map.risk(i, j) as usize)
+                .sum::<usize>(),
+            risk
+        );
+        assert_eq!(
+            map.render(&path),
+            "1.........
+1.........
+2136511...
+......15..
+.......11.
+........3.
+........2.
+........3.
+........21
+.........1"
+        );
+    }
+
+    #[test]
+    fn test_other_multipliers() {
+        let map = TiledMap::new(Cursor::new(INPUT), 3);
+        assert_eq!((map.height(), map.width()), (30, 30));
+        assert_eq!(map.risk(29, 29), 5);
+        let (risk, path) = map.lowest_risk_path();
+        assert_eq!(risk, 170);
+        assert_eq!(path.last(), Some(&(29, 29)));
+        assert_eq!(
+            path.iter()
+                .skip(1)
+                .map(|&(i, j)| map.risk(i, j) as usize)
+                .sum::<usize>(),
+            170
+        );
+
+        let map = TiledMap::new(Cursor::new(INPUT), 2);
+        assert_eq!(map.lowest_risk_path().0, 101);
+    }
 }
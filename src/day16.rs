@@ -1,11 +1,12 @@
-use std::io::{Read, Result};
-use std::{collections::HashMap, io::BufRead};
+use std::{collections::HashMap, fmt, io::BufRead};
+
+use num_bigint::BigUint;
 
 #[derive(Debug, PartialEq, Eq)]
 enum Packet {
     Literal {
         version: usize,
-        value: usize,
+        value: BigUint,
     },
     Operator {
         version: usize,
@@ -24,98 +25,160 @@ impl Packet {
         }
     }
 
-    fn compute(&self) -> usize {
+    // The number of bits `encode` writes for the packet.
+    fn encoded_len(&self) -> usize {
         match self {
-            Packet::Literal { value, .. } => *value,
+            Packet::Literal { value, .. } => 6 + 5 * value.to_radix_be(16).len(),
+            Packet::Operator { packets, .. } => {
+                operator_header_len(packets.len())
+                    + packets.iter().map(|p| p.encoded_len()).sum::<usize>()
+            }
+        }
+    }
+
+    // The value of the expression. Offsets in errors are bit positions in the
+    // packet as `encode` writes it.
+    fn evaluate(&self) -> Result<BigUint, EvalError> {
+        self.evaluate_at(0)
+    }
+
+    fn evaluate_at(&self, offset: usize) -> Result<BigUint, EvalError> {
+        let (type_id, packets) = match self {
+            Packet::Literal { value, .. } => return Ok(value.clone()),
             Packet::Operator {
                 type_id, packets, ..
-            } => match type_id {
-                0 => packets.iter().map(|p| p.compute()).sum(),
-                1 => packets.iter().map(|p| p.compute()).product(),
-                2 => packets.iter().map(|p| p.compute()).min().unwrap(),
-                3 => packets.iter().map(|p| p.compute()).max().unwrap(),
-                4 => panic!(),
-                5 => (packets[0].compute() > packets[1].compute()).into(),
-                6 => (packets[0].compute() < packets[1].compute()).into(),
-                7 => (packets[0].compute() == packets[1].compute()).into(),
-                _ => unreachable!(),
-            },
+            } => (*type_id, packets),
+        };
+        check_operands(offset, type_id, packets.len())?;
+        let mut operands = Vec::with_capacity(packets.len());
+        let mut sub_offset = offset + operator_header_len(packets.len());
+        for packet in packets {
+            operands.push(packet.evaluate_at(sub_offset)?);
+            sub_offset += packet.encoded_len();
         }
+        Ok(match type_id {
+            0 => operands.into_iter().sum(),
+            1 => operands.into_iter().product(),
+            2 => operands.into_iter().min().unwrap(),
+            3 => operands.into_iter().max().unwrap(),
+            5 => BigUint::from((operands[0] > operands[1]) as u8),
+            6 => BigUint::from((operands[0] < operands[1]) as u8),
+            _ => BigUint::from((operands[0] == operands[1]) as u8),
+        })
     }
 }
 
-fn parse_binary(binary: &[u8]) -> usize {
-    binary.iter().fold(0, |acc, b| acc * 2 + *b as usize)
+// Version, type id, length type and the sub-packet count or bit length, as
+// `encode` picks them.
+fn operator_header_len(packets: usize) -> usize {
+    if packets < 1 << 11 {
+        7 + 11
+    } else {
+        7 + 15
+    }
+}
+
+fn operator_name(type_id: usize) -> Option<&'static str> {
+    match type_id {
+        0 => Some("sum"),
+        1 => Some("product"),
+        2 => Some("min"),
+        3 => Some("max"),
+        5 => Some("gt"),
+        6 => Some("lt"),
+        7 => Some("eq"),
+        _ => None,
+    }
 }
 
-fn parse_packet(binary: &mut &[u8]) -> Result<Packet> {
-    let version = {
-        let mut buf = [0u8; 3];
-        binary.read_exact(&mut buf)?;
-        parse_binary(&buf)
-    };
-
-    let packet_type = {
-        let mut buf = [0u8; 3];
-        binary.read_exact(&mut buf)?;
-        parse_binary(&buf)
-    };
-    match packet_type {
-        4 => {
-            let mut buf_value = Vec::new();
-            let mut buf = [1u8; 5];
-            while buf[0] != 0 {
-                binary.read_exact(&mut buf)?;
-                buf_value.extend(buf.iter().skip(1));
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Packet::Literal { value, .. } => write!(f, "{}", value),
+            Packet::Operator {
+                type_id, packets, ..
+            } => {
+                match operator_name(*type_id) {
+                    Some(name) => write!(f, "{}(", name)?,
+                    None => write!(f, "op{}(", type_id)?,
+                }
+                for (i, packet) in packets.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", packet)?;
+                }
+                write!(f, ")")
             }
-            Ok(Packet::Literal {
-                version,
-                value: parse_binary(&buf_value),
-            })
         }
-        x => {
-            // Length bit
-            let length_of_bits = {
-                let mut buf = [0u8; 1];
-                binary.read_exact(&mut buf)?;
-                match buf[0] {
-                    0 => 15,
-                    1 => 11,
-                    _ => panic!(),
-                }
-            };
-            let sub_packets = if length_of_bits == 11 {
-                let number_of_packets = {
-                    let mut buf = vec![0; length_of_bits];
-                    binary.read_exact(&mut buf).unwrap();
-                    parse_binary(&buf)
-                };
-                (0..number_of_packets)
-                    .map(|_x| parse_packet(binary).unwrap())
-                    .collect()
-            } else {
-                let length_of_bits = {
-                    let mut buf = vec![0; length_of_bits];
-                    binary.read_exact(&mut buf).unwrap();
-                    parse_binary(&buf)
-                };
+    }
+}
+
+#[allow(dead_code)]
+fn push_binary(bits: &mut Vec<u8>, value: usize, width: usize) {
+    bits.extend((0..width).rev().map(|i| ((value >> i) & 1) as u8));
+}
 
-                let mut buf = vec![0; length_of_bits];
-                binary.read_exact(&mut buf).unwrap();
-                let mut packets = Vec::new();
-                let mut sub_packets_buf = &buf[..];
-                while !sub_packets_buf.is_empty() && !buf.iter().all(|x| x == &0) {
-                    packets.push(parse_packet(&mut sub_packets_buf)?);
+#[allow(dead_code)]
+impl Packet {
+    // Operators use the sub-packet count (length type 1) as its header is
+    // shorter, falling back to the bit length (length type 0) when there are
+    // too many sub-packets to count in 11 bits.
+    fn encode(&self, bits: &mut Vec<u8>) {
+        match self {
+            Packet::Literal { version, value } => {
+                push_binary(bits, *version, 3);
+                push_binary(bits, 4, 3);
+                let groups = value.to_radix_be(16);
+                for (i, &group) in groups.iter().enumerate() {
+                    bits.push((i + 1 < groups.len()) as u8);
+                    push_binary(bits, group as usize, 4);
                 }
-                packets
-            };
-            Ok(Packet::Operator {
+            }
+            Packet::Operator {
                 version,
-                type_id: x,
-                packets: sub_packets,
-            })
+                type_id,
+                packets,
+            } => {
+                push_binary(bits, *version, 3);
+                push_binary(bits, *type_id, 3);
+                if packets.len() < 1 << 11 {
+                    bits.push(1);
+                    push_binary(bits, packets.len(), 11);
+                    for packet in packets {
+                        packet.encode(bits);
+                    }
+                } else {
+                    let mut sub_bits = Vec::new();
+                    for packet in packets {
+                        packet.encode(&mut sub_bits);
+                    }
+                    assert!(sub_bits.len() < 1 << 15, "Too many sub-packets to encode");
+                    bits.push(0);
+                    push_binary(bits, sub_bits.len(), 15);
+                    bits.extend(sub_bits);
+                }
+            }
         }
     }
+
+    // The packet as a hexadecimal transmission, padded with zeros to a whole
+    // number of bytes.
+    fn to_hex(&self) -> String {
+        let mut bits = Vec::new();
+        self.encode(&mut bits);
+        bits.resize(bits.len().div_ceil(8) * 8, 0);
+        bits.chunks(4)
+            .map(|chunk| {
+                let nibble = chunk.iter().fold(0, |acc, b| acc * 2 + *b as u32);
+                char::from_digit(nibble, 16).unwrap().to_ascii_uppercase()
+            })
+            .collect()
+    }
+}
+
+fn parse_binary(binary: &[u8]) -> usize {
+    binary.iter().fold(0, |acc, b| acc * 2 + *b as usize)
 }
 
 fn parse_hex(mut input: impl BufRead) -> Vec<u8> {
@@ -146,18 +209,280 @@ fn parse_hex(mut input: impl BufRead) -> Vec<u8> {
     binary
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum EvalError {
+    // A field, or the sub-packet bits of a length type 0 operator, does not
+    // fit in what is left of the transmission (or of the enclosing operator).
+    Truncated {
+        offset: usize,
+    },
+    // A comparison without exactly two operands.
+    Arity {
+        offset: usize,
+        type_id: usize,
+        operands: usize,
+    },
+    // A sum, product, min or max without any operands.
+    Empty {
+        offset: usize,
+        type_id: usize,
+    },
+    // An operator with the literal type id, or one past 7.
+    UnknownType {
+        offset: usize,
+        type_id: usize,
+    },
+    // A set bit in the padding after the outermost packet.
+    TrailingBits {
+        offset: usize,
+    },
+}
+
+// Reads packets straight from the bits of a transmission, so literals of any
+// length are kept exactly. Offsets in errors are bit positions in the
+// transmission.
+struct Parser<'a> {
+    bits: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn read(&mut self, width: usize, end: usize) -> Result<usize, EvalError> {
+        if self.pos + width > end {
+            return Err(EvalError::Truncated { offset: self.pos });
+        }
+        let value = parse_binary(&self.bits[self.pos..self.pos + width]);
+        self.pos += width;
+        Ok(value)
+    }
+
+    fn packet(&mut self, end: usize) -> Result<Packet, EvalError> {
+        let offset = self.pos;
+        let version = self.read(3, end)?;
+        let type_id = self.read(3, end)?;
+        if type_id == 4 {
+            let mut value = BigUint::default();
+            loop {
+                let more = self.read(1, end)?;
+                value = (value << 4u8) + self.read(4, end)?;
+                if more == 0 {
+                    return Ok(Packet::Literal { version, value });
+                }
+            }
+        }
+
+        let mut packets = Vec::new();
+        if self.read(1, end)? == 0 {
+            let length = self.read(15, end)?;
+            let sub_end = self.pos + length;
+            if sub_end > end {
+                return Err(EvalError::Truncated { offset: self.pos });
+            }
+            while self.pos < sub_end {
+                packets.push(self.packet(sub_end)?);
+            }
+        } else {
+            for _ in 0..self.read(11, end)? {
+                packets.push(self.packet(end)?);
+            }
+        }
+        check_operands(offset, type_id, packets.len())?;
+        Ok(Packet::Operator {
+            version,
+            type_id,
+            packets,
+        })
+    }
+}
+
+// Whether an operator of this type can be evaluated with this many operands.
+fn check_operands(offset: usize, type_id: usize, operands: usize) -> Result<(), EvalError> {
+    match type_id {
+        4 | 8.. => Err(EvalError::UnknownType { offset, type_id }),
+        5..=7 if operands != 2 => Err(EvalError::Arity {
+            offset,
+            type_id,
+            operands,
+        }),
+        _ if operands == 0 => Err(EvalError::Empty { offset, type_id }),
+        _ => Ok(()),
+    }
+}
+
+// Parses the outermost packet of a transmission, which may only be followed
+// by zero padding.
+fn parse_packet(bits: &[u8]) -> Result<Packet, EvalError> {
+    let mut parser = Parser { bits, pos: 0 };
+    let packet = parser.packet(bits.len())?;
+    match bits[parser.pos..].iter().position(|&b| b != 0) {
+        Some(i) => Err(EvalError::TrailingBits {
+            offset: parser.pos + i,
+        }),
+        None => Ok(packet),
+    }
+}
+
+fn evaluate(bits: &[u8]) -> Result<BigUint, EvalError> {
+    parse_packet(bits)?.evaluate()
+}
+
+// Compiles infix arithmetic such as `(1 + 2) * max(3, 4) < 9` into a packet
+// tree. `+` and `*` become sum and product packets, `<`, `>` and `==` the
+// comparisons, and the operator names can be called directly as functions.
+// Every packet gets version 0. Errors give the character offset of the
+// problem.
+struct Compiler<'a> {
+    source: &'a str,
+    pos: usize,
+}
+
+#[allow(dead_code)]
+impl<'a> Compiler<'a> {
+    fn compile(source: &'a str) -> Result<Packet, String> {
+        let mut compiler = Compiler { source, pos: 0 };
+        let packet = compiler.comparison()?;
+        compiler.skip_whitespace();
+        match compiler.peek() {
+            None => Ok(packet),
+            Some(c) => Err(compiler.error(&format!("unexpected {:?}", c))),
+        }
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("{} at {}", message, self.source[..self.pos].chars().count())
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.source[self.pos..].starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {:?}", token)))
+        }
+    }
+
+    fn operator(type_id: usize, packets: Vec<Packet>) -> Packet {
+        Packet::Operator {
+            version: 0,
+            type_id,
+            packets,
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Packet, String> {
+        let left = self.additive()?;
+        let type_id = if self.eat("==") {
+            7
+        } else if self.eat("<") {
+            6
+        } else if self.eat(">") {
+            5
+        } else {
+            return Ok(left);
+        };
+        let right = self.additive()?;
+        Ok(Compiler::operator(type_id, vec![left, right]))
+    }
+
+    fn additive(&mut self) -> Result<Packet, String> {
+        let mut terms = vec![self.term()?];
+        while self.eat("+") {
+            terms.push(self.term()?);
+        }
+        Ok(if terms.len() == 1 {
+            terms.pop().unwrap()
+        } else {
+            Compiler::operator(0, terms)
+        })
+    }
+
+    fn term(&mut self) -> Result<Packet, String> {
+        let mut factors = vec![self.factor()?];
+        while self.eat("*") {
+            factors.push(self.factor()?);
+        }
+        Ok(if factors.len() == 1 {
+            factors.pop().unwrap()
+        } else {
+            Compiler::operator(1, factors)
+        })
+    }
+
+    fn factor(&mut self) -> Result<Packet, String> {
+        self.skip_whitespace();
+        let start = self.pos;
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let packet = self.comparison()?;
+                self.expect(")")?;
+                Ok(packet)
+            }
+            Some(c) if c.is_ascii_digit() => {
+                while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    self.pos += 1;
+                }
+                // Only digits, and literals can be any length.
+                let value = self.source[start..self.pos].parse().unwrap();
+                Ok(Packet::Literal { version: 0, value })
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                    self.pos += 1;
+                }
+                let name = &self.source[start..self.pos];
+                let Some(type_id) = (0..8).find(|&id| operator_name(id) == Some(name)) else {
+                    self.pos = start;
+                    return Err(self.error(&format!("unknown function {:?}", name)));
+                };
+                self.expect("(")?;
+                let mut arguments = vec![self.comparison()?];
+                while self.eat(",") {
+                    arguments.push(self.comparison()?);
+                }
+                self.expect(")")?;
+                if type_id >= 5 && arguments.len() != 2 {
+                    self.pos = start;
+                    return Err(self.error(&format!("{} takes exactly two arguments", name)));
+                }
+                Ok(Compiler::operator(type_id, arguments))
+            }
+            Some(c) => Err(self.error(&format!("unexpected {:?}", c))),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+}
+
 pub fn star_one(input: impl BufRead) -> usize {
     let binary = parse_hex(input);
-    let packets = parse_packet(&mut &binary[..]).unwrap();
+    let packets = parse_packet(&binary).unwrap();
 
     packets.sum_version()
 }
 
 pub fn star_two(input: impl BufRead) -> usize {
     let binary = parse_hex(input);
-    let packets = parse_packet(&mut &binary[..]).unwrap();
 
-    packets.compute()
+    usize::try_from(evaluate(&binary).unwrap()).unwrap()
 }
 
 #[cfg(test)]
@@ -171,10 +496,10 @@ mod tests {
             1, 1, 0, 1, 0, 0, 1, 0, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 1, 0, 1, 0, 0, 0,
         ];
         assert_eq!(
-            parse_packet(&mut &input[..]).unwrap(),
+            parse_packet(&input[..]).unwrap(),
             Packet::Literal {
                 version: 6,
-                value: 2021
+                value: BigUint::from(2021u16)
             }
         );
 
@@ -183,18 +508,18 @@ mod tests {
             1, 0, 1, 0, 0, 1, 0, 1, 0, 0, 1, 0, 0, 0, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0,
         ];
         assert_eq!(
-            parse_packet(&mut &input[..]).unwrap(),
+            parse_packet(&input[..]).unwrap(),
             Packet::Operator {
                 version: 1,
                 type_id: 6,
                 packets: vec![
                     Packet::Literal {
                         version: 6,
-                        value: 10
+                        value: BigUint::from(10u16)
                     },
                     Packet::Literal {
                         version: 2,
-                        value: 20
+                        value: BigUint::from(20u16)
                     }
                 ]
             }
@@ -206,22 +531,22 @@ mod tests {
         ];
 
         assert_eq!(
-            parse_packet(&mut &input[..]).unwrap(),
+            parse_packet(&input[..]).unwrap(),
             Packet::Operator {
                 version: 7,
                 type_id: 3,
                 packets: vec![
                     Packet::Literal {
                         version: 2,
-                        value: 1
+                        value: BigUint::from(1u16)
                     },
                     Packet::Literal {
                         version: 4,
-                        value: 2
+                        value: BigUint::from(2u16)
                     },
                     Packet::Literal {
                         version: 1,
-                        value: 3
+                        value: BigUint::from(3u16)
                     }
                 ]
             }
@@ -263,6 +588,250 @@ mod tests {
 
         let input = b"A0016C880162017C3686B18A3D4780";
         assert_eq!(star_one(Cursor::new(input)), 31);
+
+        // Literals longer than 64 bits.
+        let packet = Packet::Literal {
+            version: 5,
+            value: BigUint::from(u128::MAX),
+        };
+        assert_eq!(star_one(Cursor::new(packet.to_hex())), 5);
+    }
+
+    fn decode(hex: &str) -> Packet {
+        let binary = parse_hex(Cursor::new(hex.to_owned()));
+        parse_packet(&binary).unwrap()
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(decode("D2FE28").to_string(), "2021");
+        assert_eq!(decode("EE00D40C823060").to_string(), "max(1, 2, 3)");
+        assert_eq!(
+            decode("9C0141080250320F1802104A08").to_string(),
+            "eq(sum(1, 3), product(2, 2))"
+        );
+        assert_eq!(decode("C200B40A82").to_string(), "sum(1, 2)");
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        // Already count based, so these come back exactly.
+        for hex in ["D2FE28", "EE00D40C823060"] {
+            assert_eq!(decode(hex).to_hex(), hex);
+        }
+
+        for hex in [
+            "38006F45291200",
+            "8A004A801A8002F478",
+            "620080001611562C8802118E34",
+            "C0015000016115A2E0802F182340",
+            "A0016C880162017C3686B18A3D4780",
+            "C200B40A82",
+            "04005AC33890",
+            "880086C3E88112",
+            "CE00C43D881120",
+            "D8005AC2A8F0",
+            "F600BC2D8F",
+            "9C005AC2F8F0",
+            "9C0141080250320F1802104A08",
+        ] {
+            let packet = decode(hex);
+            assert_eq!(decode(&packet.to_hex()), packet);
+            assert_eq!(evaluate(&bits(&packet.to_hex())), evaluate(&bits(hex)));
+        }
+    }
+
+    #[test]
+    fn test_encode_literals_and_large_operators() {
+        for value in [0u128, 1, 15, 16, 2021, u64::MAX as u128, u128::MAX] {
+            let packet = Packet::Literal {
+                version: 5,
+                value: BigUint::from(value),
+            };
+            assert_eq!(decode(&packet.to_hex()), packet);
+        }
+
+        let packet = Packet::Operator {
+            version: 1,
+            type_id: 0,
+            packets: (0..2500)
+                .map(|i| Packet::Literal {
+                    version: 0,
+                    value: BigUint::from(i % 7u32),
+                })
+                .collect(),
+        };
+        let mut bits = Vec::new();
+        packet.encode(&mut bits);
+        assert_eq!(bits[6], 0);
+        assert_eq!(decode(&packet.to_hex()), packet);
+    }
+
+    fn bits(hex: &str) -> Vec<u8> {
+        parse_hex(Cursor::new(hex.to_owned()))
+    }
+
+    fn value(packet: &Packet) -> BigUint {
+        packet.evaluate().unwrap()
+    }
+
+    #[test]
+    fn test_evaluate() {
+        for (hex, expected) in [
+            ("C200B40A82", 3u32),
+            ("04005AC33890", 54),
+            ("880086C3E88112", 7),
+            ("CE00C43D881120", 9),
+            ("9C0141080250320F1802104A08", 1),
+        ] {
+            assert_eq!(evaluate(&bits(hex)), Ok(BigUint::from(expected)));
+        }
+
+        // Sums past usize::MAX and literals longer than 64 bits.
+        let packet = Compiler::compile("9223372036854775808 + 9223372036854775808").unwrap();
+        assert_eq!(
+            evaluate(&bits(&packet.to_hex())),
+            Ok(BigUint::from(1u8) << 64u8)
+        );
+        let mut literal = Vec::new();
+        push_binary(&mut literal, 4, 6);
+        for group in (0..20).rev() {
+            literal.push((group > 0) as u8);
+            push_binary(&mut literal, 15, 4);
+        }
+        assert_eq!(evaluate(&literal), Ok((BigUint::from(1u8) << 80u8) - 1u8));
+    }
+
+    #[test]
+    fn test_evaluate_errors() {
+        let literal = |value: u8| Packet::Literal {
+            version: 0,
+            value: BigUint::from(value),
+        };
+        let operator = |type_id, packets| Packet::Operator {
+            version: 0,
+            type_id,
+            packets,
+        };
+
+        let packet = operator(6, vec![literal(1)]);
+        let error = EvalError::Arity {
+            offset: 0,
+            type_id: 6,
+            operands: 1,
+        };
+        assert_eq!(packet.evaluate(), Err(error.clone()));
+        assert_eq!(evaluate(&bits(&packet.to_hex())), Err(error));
+
+        // The max packet starts after the 18 bit sum header and the 11 bit
+        // literal.
+        let packet = operator(0, vec![literal(1), operator(3, vec![])]);
+        let error = EvalError::Empty {
+            offset: 29,
+            type_id: 3,
+        };
+        assert_eq!(packet.evaluate(), Err(error.clone()));
+        assert_eq!(evaluate(&bits(&packet.to_hex())), Err(error));
+
+        let packet = operator(1, vec![literal(2), operator(4, vec![literal(3)])]);
+        assert_eq!(
+            packet.evaluate(),
+            Err(EvalError::UnknownType {
+                offset: 29,
+                type_id: 4
+            })
+        );
+
+        assert_eq!(
+            evaluate(&bits("D2FE")),
+            Err(EvalError::Truncated { offset: 16 })
+        );
+        assert_eq!(
+            evaluate(&bits("D2FE29")),
+            Err(EvalError::TrailingBits { offset: 23 })
+        );
+
+        // A length type 0 operator claiming more sub-packet bits than exist.
+        let mut header = Vec::new();
+        push_binary(&mut header, 0, 7);
+        push_binary(&mut header, 100, 15);
+        header.extend([0; 8]);
+        assert_eq!(evaluate(&header), Err(EvalError::Truncated { offset: 22 }));
+
+        // Parsing fails the same way, so part one does not panic either.
+        assert_eq!(
+            parse_packet(&header),
+            Err(EvalError::Truncated { offset: 22 })
+        );
+        assert_eq!(
+            parse_packet(&bits("D2FE")),
+            Err(EvalError::Truncated { offset: 16 })
+        );
+    }
+
+    #[test]
+    fn test_compile() {
+        let packet = Compiler::compile("(1 + 2) * max(3, 4) < 9").unwrap();
+        assert_eq!(packet.to_string(), "lt(product(sum(1, 2), max(3, 4)), 9)");
+        assert_eq!(value(&packet), BigUint::from(0u8));
+
+        let packet = Compiler::compile("1 + 2 * 3 + min(7, 8, 9) == 14").unwrap();
+        assert_eq!(
+            packet.to_string(),
+            "eq(sum(1, product(2, 3), min(7, 8, 9)), 14)"
+        );
+        assert_eq!(value(&packet), BigUint::from(1u8));
+
+        let packet = Compiler::compile("gt(sum(1,2),product(1,2))").unwrap();
+        assert_eq!(packet.to_string(), "gt(sum(1, 2), product(1, 2))");
+        assert_eq!(value(&packet), BigUint::from(1u8));
+    }
+
+    #[test]
+    fn test_compile_to_hex() {
+        for (source, expected) in [
+            ("2021", 2021),
+            ("1 + 2", 3),
+            ("max(1, 2, 3) * 4", 12),
+            ("(1 < 2) + (3 > 4) + (5 == 5)", 2),
+        ] {
+            let packet = Compiler::compile(source).unwrap();
+            let hex = packet.to_hex();
+            assert_eq!(star_two(Cursor::new(hex.clone())), expected);
+            assert_eq!(decode(&hex), packet);
+        }
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        assert_eq!(
+            Compiler::compile("1 +"),
+            Err("unexpected end of input at 3".to_string())
+        );
+        assert_eq!(
+            Compiler::compile("(1 + 2"),
+            Err("expected \")\" at 6".to_string())
+        );
+        assert_eq!(
+            Compiler::compile("1 - 2"),
+            Err("unexpected '-' at 2".to_string())
+        );
+        assert_eq!(
+            Compiler::compile("foo(1)"),
+            Err("unknown function \"foo\" at 0".to_string())
+        );
+        assert_eq!(
+            Compiler::compile("1 + lt(1, 2, 3)"),
+            Err("lt takes exactly two arguments at 4".to_string())
+        );
+        assert!(Compiler::compile("1 < 2 < 3").is_err());
+
+        // Offsets count characters, not bytes.
+        assert_eq!(Compiler::compile("1 +\u{a0}2"), Compiler::compile("1 + 2"));
+        assert_eq!(
+            Compiler::compile("\u{a0}\u{3000}1 -"),
+            Err("unexpected '-' at 4".to_string())
+        );
     }
 
     #[test]
@@ -1,84 +1,139 @@
-use std::{cmp::Ordering, io::BufRead};
-
-fn is_within(ix: isize, iy: isize, target: (isize, isize, isize, isize)) -> Option<isize> {
-    let mut dx = ix;
-    let mut dy = iy;
-
-    let mut c_x = 0;
-    let mut c_y = 0;
-    let mut max_height = 0;
-    loop {
-        // println!("{} {}", dx, dy);
-        if target.0 <= c_x && c_x <= target.1 && target.2 <= c_y && c_y <= target.3 {
-            // println!("{} {} - {}", ix, iy, max_height);
-            return Some(max_height);
-        }
-        if dx == 0 && (c_x < target.0 || c_x > target.1 || c_y < target.3) {
-            break;
-        }
-        c_x += dx;
-        c_y += dy;
+use std::{collections::BTreeSet, io::BufRead, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Target {
+    x_min: isize,
+    x_max: isize,
+    y_min: isize,
+    y_max: isize,
+}
 
-        max_height = max_height.max(c_y);
+impl FromStr for Target {
+    type Err = String;
 
-        dx = match dx.cmp(&0) {
-            Ordering::Less => dx + 1,
-            Ordering::Equal => 0,
-            Ordering::Greater => dx - 1,
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let range = |r: &str| -> Result<(isize, isize), String> {
+            let (lower, upper) = r
+                .split_once("..")
+                .ok_or_else(|| format!("Invalid range {:?}", r))?;
+            let lower = lower.trim().parse::<isize>().map_err(|e| e.to_string())?;
+            let upper = upper.trim().parse::<isize>().map_err(|e| e.to_string())?;
+            Ok((lower.min(upper), lower.max(upper)))
         };
-        dy -= 1;
+        let s = s
+            .trim()
+            .strip_prefix("target area: x=")
+            .ok_or("Missing \"target area: x=\"")?;
+        let (left, right) = s.split_once(", y=").ok_or("Missing \", y=\"")?;
+        let (x_min, x_max) = range(left)?;
+        let (y_min, y_max) = range(right)?;
+        Ok(Target {
+            x_min,
+            x_max,
+            y_min,
+            y_max,
+        })
+    }
+}
+
+fn triangle(n: isize) -> isize {
+    n * (n + 1) / 2
+}
+
+fn div_ceil(a: isize, b: isize) -> isize {
+    -(-a).div_euclid(b)
+}
+
+// Non-negative x velocities that are within a..=b after exactly `steps`
+// steps. Those still moving travel `steps * vx - triangle(steps - 1)`, and
+// those that have come to a stop rest at `triangle(vx)`.
+fn forward_velocities(a: isize, b: isize, steps: isize) -> Vec<isize> {
+    let a = a.max(0);
+    if b < a {
+        return Vec::new();
     }
-    None
+    let travelled = triangle(steps - 1);
+    let moving = div_ceil(a + travelled, steps).max(steps)..=(b + travelled).div_euclid(steps);
+    let stopped = (0..steps).filter(|&vx| (a..=b).contains(&triangle(vx)));
+    stopped.chain(moving).collect()
 }
 
-pub fn star_one(mut input: impl BufRead) -> usize {
-    let target = {
-        let mut buf = String::new();
-
-        let _res = input.read_to_string(&mut buf);
-        let s = buf.strip_prefix("target area: x=").unwrap();
-        let (left, right) = s.split_once(", y=").unwrap();
-        let (lower_x, upper_x) = left.split_once("..").unwrap();
-        let (lower_y, upper_y) = right.split_once("..").unwrap();
-        (
-            lower_x.parse::<isize>().unwrap(),
-            upper_x.parse::<isize>().unwrap(),
-            lower_y.parse::<isize>().unwrap(),
-            upper_y.parse::<isize>().unwrap(),
-        )
-    };
-
-    let mut max_height = 0;
-    for y in 0..1000 {
-        for x in 0..=target.1 {
-            if let Some(height) = is_within(x, y, target) {
-                max_height = max_height.max(height);
+impl Target {
+    fn x_velocities(&self, steps: isize) -> impl Iterator<Item = isize> {
+        let right = forward_velocities(self.x_min, self.x_max, steps);
+        let left = forward_velocities(-self.x_max, -self.x_min, steps);
+        right
+            .into_iter()
+            .chain(left.into_iter().map(|vx| -vx).filter(|&vx| vx != 0))
+    }
+
+    fn y_velocities(&self, steps: isize) -> std::ops::RangeInclusive<isize> {
+        let travelled = triangle(steps - 1);
+        div_ceil(self.y_min + travelled, steps)..=(self.y_max + travelled).div_euclid(steps)
+    }
+
+    // Whether some x velocity brings the probe to rest inside the target.
+    fn can_stop_inside(&self) -> bool {
+        let stops = |a: isize, b: isize| (0..=b.max(0)).any(|vx| (a..=b).contains(&triangle(vx)));
+        stops(self.x_min, self.x_max) || stops(-self.x_max, -self.x_min)
+    }
+
+    // The last step on which any velocity can still be inside the target, or
+    // `None` when there is no such step. That happens when the target spans
+    // y = 0 and the probe can stop inside it: every upward throw comes back
+    // down through y = 0, so infinitely many velocities hit.
+    fn last_step(&self) -> Option<isize> {
+        // Past these steps even the highest throw that can come back into the
+        // y range is below it.
+        let y_bound = if self.y_max < 0 {
+            Some(-2 * self.y_min)
+        } else if self.y_min > 0 {
+            Some(2 * self.y_max)
+        } else {
+            None
+        };
+        if self.can_stop_inside() {
+            return y_bound;
+        }
+        // The probe is never slower than its first step while it is moving,
+        // so it cannot still be moving at the target after more steps than
+        // the target is far away.
+        let x_bound = self.x_min.abs().max(self.x_max.abs());
+        Some(y_bound.map_or(x_bound, |y| y.min(x_bound)))
+    }
+
+    // Every launch velocity that puts the probe inside the target after some
+    // number of steps, or `None` when there are infinitely many.
+    fn velocities(&self) -> Option<BTreeSet<(isize, isize)>> {
+        let mut velocities = BTreeSet::new();
+        for steps in 1..=self.last_step()? {
+            let ys = self.y_velocities(steps);
+            for vx in self.x_velocities(steps) {
+                velocities.extend(ys.clone().map(|vy| (vx, vy)));
             }
         }
+        Some(velocities)
     }
-    max_height as usize
 }
 
-pub fn star_two(mut input: impl BufRead) -> usize {
-    let target = {
-        let mut buf = String::new();
-
-        let _res = input.read_to_string(&mut buf);
-        let s = buf.strip_prefix("target area: x=").unwrap();
-        let (left, right) = s.split_once(", y=").unwrap();
-        let (lower_x, upper_x) = left.split_once("..").unwrap();
-        let (lower_y, upper_y) = right.split_once("..").unwrap();
-        (
-            lower_x.parse::<isize>().unwrap(),
-            upper_x.parse::<isize>().unwrap(),
-            lower_y.parse::<isize>().unwrap(),
-            upper_y.parse::<isize>().unwrap(),
-        )
-    };
-
-    ((target.3 * 10)..100)
-        .flat_map(|y| (0..=target.1).filter_map(move |x| is_within(x, y, target)))
-        .count()
+// The highest point reached by any of the velocities, starting from y = 0.
+fn max_height(velocities: &BTreeSet<(isize, isize)>) -> Option<isize> {
+    velocities.iter().map(|&(_, vy)| triangle(vy.max(0))).max()
+}
+
+fn parse_input(mut input: impl BufRead) -> Target {
+    let mut buf = String::new();
+    let _res = input.read_to_string(&mut buf);
+    buf.parse().unwrap()
+}
+
+pub fn star_one(input: impl BufRead) -> usize {
+    let velocities = parse_input(input).velocities().unwrap();
+    max_height(&velocities).unwrap() as usize
+}
+
+pub fn star_two(input: impl BufRead) -> usize {
+    parse_input(input).velocities().unwrap().len()
 }
 
 #[cfg(test)]
@@ -97,4 +152,66 @@ mod tests {
         let input = b"target area: x=20..30, y=-10..-5";
         assert_eq!(star_two(Cursor::new(input)), 112);
     }
+
+    fn hits(target: &Target, mut vx: isize, mut vy: isize, steps: usize) -> bool {
+        let (mut x, mut y) = (0, 0);
+        for _ in 0..steps {
+            x += vx;
+            y += vy;
+            vx -= vx.signum();
+            vy -= 1;
+            if (target.x_min..=target.x_max).contains(&x)
+                && (target.y_min..=target.y_max).contains(&y)
+            {
+                return true;
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn test_matches_simulation() {
+        for input in [
+            "target area: x=20..30, y=-10..-5",
+            "target area: x=-30..-20, y=-10..-5",
+            "target area: x=20..30, y=5..10",
+            "target area: x=-30..-20, y=5..10",
+            "target area: x=-5..5, y=-10..-5",
+            "target area: x=-3..4, y=2..6",
+            "target area: x=11..12, y=-3..3",
+            "target area: x=-12..-11, y=-3..3",
+        ] {
+            let target: Target = input.parse().unwrap();
+            let bound = [target.x_min, target.x_max, target.y_min, target.y_max]
+                .iter()
+                .map(|v| v.abs())
+                .max()
+                .unwrap()
+                + 1;
+            let expected: BTreeSet<(isize, isize)> = (-bound..=bound)
+                .flat_map(|vx| (-bound..=bound).map(move |vy| (vx, vy)))
+                .filter(|&(vx, vy)| hits(&target, vx, vy, 4 * bound as usize + 10))
+                .collect();
+            assert_eq!(target.velocities(), Some(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_max_height() {
+        let target: Target = "target area: x=-30..-20, y=-10..-5".parse().unwrap();
+        assert_eq!(max_height(&target.velocities().unwrap()), Some(45));
+        let target: Target = "target area: x=20..30, y=5..10".parse().unwrap();
+        assert_eq!(max_height(&target.velocities().unwrap()), Some(55));
+        let target: Target = "target area: x=1..1, y=-10..-5".parse().unwrap();
+        assert_eq!(max_height(&target.velocities().unwrap()), Some(45));
+    }
+
+    #[test]
+    fn test_unbounded() {
+        // Stopping at x = 21 and falling back through y = 0 from any height.
+        let target: Target = "target area: x=20..21, y=-5..5".parse().unwrap();
+        assert_eq!(target.velocities(), None);
+        let target: Target = "target area: x=-2..2, y=-1..1".parse().unwrap();
+        assert_eq!(target.velocities(), None);
+    }
 }
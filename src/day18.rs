@@ -1,13 +1,4 @@
 use itertools::Itertools;
-use nom::{
-    branch::alt,
-    // see the "streaming/complete" paragraph lower for an explanation of these submodules
-    character::complete::{char, digit1},
-    combinator::map_res,
-    sequence::{delimited, separated_pair},
-    IResult,
-    Parser,
-};
 use std::{collections::VecDeque, fmt::Display, io::BufRead, ops::Add, str::FromStr};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -98,33 +89,211 @@ impl SnailfishNumber {
     }
 }
 
-fn parse_value(input: &str) -> IResult<&str, SnailfishNumber> {
-    map_res(digit1, |v: &str| {
-        v.parse::<usize>().map(SnailfishNumber::Value)
-    })
-    .parse(input)
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ParseError {
+    UnexpectedEnd {
+        position: usize,
+    },
+    Unexpected {
+        position: usize,
+        found: char,
+        expected: &'static str,
+    },
+    // The line ends before the bracket opened at `position` is closed.
+    Unclosed {
+        position: usize,
+    },
+    // A closing bracket after the number is already complete.
+    Unmatched {
+        position: usize,
+    },
+    ValueTooLarge {
+        position: usize,
+    },
 }
 
-fn parse_snailfish_pair(input: &str) -> IResult<&str, SnailfishNumber> {
-    let inner = separated_pair(
-        alt((parse_snailfish_pair, parse_value)),
-        char(','),
-        alt((parse_snailfish_pair, parse_value)),
-    );
-    let (rest, numbers) = delimited(char('['), inner, char(']')).parse(input)?;
-    let value = SnailfishNumber::Pair(Box::new(numbers.0), Box::new(numbers.1));
-    Ok((rest, value))
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::UnexpectedEnd { position } => {
+                write!(f, "Unexpected end of input at {}", position)
+            }
+            ParseError::Unexpected {
+                position,
+                found,
+                expected,
+            } => write!(
+                f,
+                "Expected {} at {}, found {:?}",
+                expected, position, found
+            ),
+            ParseError::Unclosed { position } => write!(f, "Unclosed '[' at {}", position),
+            ParseError::Unmatched { position } => write!(f, "Unmatched ']' at {}", position),
+            ParseError::ValueTooLarge { position } => write!(f, "Value too large at {}", position),
+        }
+    }
+}
+
+// Positions in errors are character columns. `columns` collects the column of
+// every pair and regular number, in the order they are parsed.
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+    columns: Vec<usize>,
+}
+
+impl Parser<'_> {
+    fn column(&self, position: usize) -> usize {
+        self.input[..position].chars().count()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn expect(&mut self, c: char, open: usize) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(found) if found == c => {
+                self.position += 1;
+                Ok(())
+            }
+            Some(found) => Err(ParseError::Unexpected {
+                position: self.column(self.position),
+                found,
+                expected: if c == ',' { "','" } else { "']'" },
+            }),
+            None => Err(ParseError::Unclosed {
+                position: self.column(open),
+            }),
+        }
+    }
+
+    fn number(&mut self) -> Result<SnailfishNumber, ParseError> {
+        let start = self.position;
+        let column = self.column(start);
+        self.columns.push(column);
+        match self.peek() {
+            Some('[') => {
+                self.position += 1;
+                let left = self.number()?;
+                self.expect(',', start)?;
+                let right = self.number()?;
+                self.expect(']', start)?;
+                Ok(SnailfishNumber::Pair(Box::new(left), Box::new(right)))
+            }
+            Some(c) if c.is_ascii_digit() => {
+                while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    self.position += 1;
+                }
+                self.input[start..self.position]
+                    .parse()
+                    .map(SnailfishNumber::Value)
+                    .map_err(|_| ParseError::ValueTooLarge { position: column })
+            }
+            Some(found) => Err(ParseError::Unexpected {
+                position: column,
+                found,
+                expected: "a number or '['",
+            }),
+            None => Err(ParseError::UnexpectedEnd { position: column }),
+        }
+    }
+}
+
+impl SnailfishNumber {
+    // The whole line has to be a single pair. Also returns the column of every
+    // pair and regular number, in pre-order.
+    fn parse_with_columns(s: &str) -> Result<(Self, Vec<usize>), ParseError> {
+        let mut parser = Parser {
+            input: s,
+            position: 0,
+            columns: Vec::new(),
+        };
+        match parser.peek() {
+            Some('[') => {}
+            Some(found) => {
+                return Err(ParseError::Unexpected {
+                    position: 0,
+                    found,
+                    expected: "'['",
+                })
+            }
+            None => return Err(ParseError::UnexpectedEnd { position: 0 }),
+        }
+        let number = parser.number()?;
+        match parser.peek() {
+            None => Ok((number, parser.columns)),
+            Some(']') => Err(ParseError::Unmatched {
+                position: parser.column(parser.position),
+            }),
+            Some(found) => Err(ParseError::Unexpected {
+                position: parser.column(parser.position),
+                found,
+                expected: "end of input",
+            }),
+        }
+    }
 }
 
 impl FromStr for SnailfishNumber {
-    type Err = String;
+    type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (rest, snailfishnumber) = parse_snailfish_pair(s).unwrap();
-        assert_eq!(rest.len(), 0);
+        SnailfishNumber::parse_with_columns(s).map(|(number, _)| number)
+    }
+}
 
-        Ok(snailfishnumber)
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Problem {
+    Invalid(ParseError),
+    // A pair nested inside four others, which would explode.
+    TooDeep { position: usize },
+    // A regular number that would split.
+    TooLarge { position: usize, value: usize },
+}
+
+// Checks that every line of the homework is a snailfish number already in
+// reduced form, returning the problems found with their line index.
+#[allow(dead_code)]
+fn check_homework(input: impl BufRead) -> Vec<(usize, Problem)> {
+    // Walks the number in the order it was parsed, `depth` being the number
+    // of pairs around `number`.
+    fn check(
+        number: &SnailfishNumber,
+        depth: usize,
+        columns: &mut impl Iterator<Item = usize>,
+        problems: &mut Vec<Problem>,
+    ) {
+        let position = columns.next().unwrap();
+        match number {
+            SnailfishNumber::Pair(left, right) => {
+                if depth == 4 {
+                    problems.push(Problem::TooDeep { position });
+                }
+                check(left, depth + 1, columns, problems);
+                check(right, depth + 1, columns, problems);
+            }
+            &SnailfishNumber::Value(value) => {
+                if value > 9 {
+                    problems.push(Problem::TooLarge { position, value });
+                }
+            }
+        }
     }
+
+    let mut problems = Vec::new();
+    for (index, line) in input.lines().enumerate() {
+        let line = line.unwrap();
+        match SnailfishNumber::parse_with_columns(&line) {
+            Ok((number, columns)) => {
+                let mut found = Vec::new();
+                check(&number, 0, &mut columns.into_iter(), &mut found);
+                problems.extend(found.into_iter().map(|problem| (index, problem)));
+            }
+            Err(e) => problems.push((index, Problem::Invalid(e))),
+        }
+    }
+    problems
 }
 
 impl Display for SnailfishNumber {
@@ -148,6 +317,156 @@ impl Add for SnailfishNumber {
     }
 }
 
+// The same numbers stored as their regular values from left to right, each
+// with the number of pairs it is nested in. Explosions and splits only ever
+// touch neighbouring values, so they work on the vector directly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FlatNumber(Vec<(usize, usize)>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Explode { left: usize, right: usize },
+    Split { value: usize },
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::Explode { left, right } => write!(f, "explode [{},{}]", left, right),
+            Action::Split { value } => write!(f, "split {}", value),
+        }
+    }
+}
+
+impl From<&SnailfishNumber> for FlatNumber {
+    fn from(number: &SnailfishNumber) -> Self {
+        fn flatten(number: &SnailfishNumber, depth: usize, leaves: &mut Vec<(usize, usize)>) {
+            match number {
+                SnailfishNumber::Value(v) => leaves.push((depth, *v)),
+                SnailfishNumber::Pair(left, right) => {
+                    flatten(left, depth + 1, leaves);
+                    flatten(right, depth + 1, leaves);
+                }
+            }
+        }
+        let mut leaves = Vec::new();
+        flatten(number, 0, &mut leaves);
+        FlatNumber(leaves)
+    }
+}
+
+impl FromStr for FlatNumber {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(FlatNumber::from(&s.parse::<SnailfishNumber>()?))
+    }
+}
+
+impl Display for FlatNumber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn write(
+            f: &mut std::fmt::Formatter<'_>,
+            leaves: &[(usize, usize)],
+            i: &mut usize,
+            depth: usize,
+        ) -> std::fmt::Result {
+            let (leaf_depth, value) = leaves[*i];
+            if leaf_depth == depth {
+                *i += 1;
+                return write!(f, "{}", value);
+            }
+            write!(f, "[")?;
+            write(f, leaves, i, depth + 1)?;
+            write!(f, ",")?;
+            write(f, leaves, i, depth + 1)?;
+            write!(f, "]")
+        }
+        write(f, &self.0, &mut 0, 0)
+    }
+}
+
+impl FlatNumber {
+    fn magnitude(&self) -> usize {
+        let mut stack: Vec<(usize, usize)> = Vec::new();
+        for &leaf in &self.0 {
+            stack.push(leaf);
+            while let [.., (a_depth, a), (b_depth, b)] = stack[..] {
+                if a_depth != b_depth {
+                    break;
+                }
+                stack.truncate(stack.len() - 2);
+                stack.push((a_depth - 1, 3 * a + 2 * b));
+            }
+        }
+        stack[0].1
+    }
+
+    // Applies the first explosion, or failing that the first split, and
+    // returns what it did. Numbers built by adding reduced numbers nest at
+    // most five deep, so the first value past depth four always starts a pair
+    // of two values.
+    fn reduce_step(&mut self) -> Option<Action> {
+        let leaves = &mut self.0;
+        // Two neighbouring leaves at the same depth are only a pair if no
+        // deeper pair comes before them, so the first such neighbours are the
+        // leftmost pair of regular numbers even in numbers nested deeper than
+        // reduction ever leaves them.
+        if let Some(i) = leaves
+            .windows(2)
+            .position(|w| w[0].0 > 4 && w[0].0 == w[1].0)
+        {
+            let (depth, left) = leaves[i];
+            let (_, right) = leaves[i + 1];
+            if i > 0 {
+                leaves[i - 1].1 += left;
+            }
+            if let Some(next) = leaves.get_mut(i + 2) {
+                next.1 += right;
+            }
+            leaves.splice(i..i + 2, [(depth - 1, 0)]);
+            return Some(Action::Explode { left, right });
+        }
+        let i = leaves.iter().position(|&(_, value)| value > 9)?;
+        let (depth, value) = leaves[i];
+        leaves.splice(
+            i..=i,
+            [(depth + 1, value / 2), (depth + 1, value - value / 2)],
+        );
+        Some(Action::Split { value })
+    }
+
+    fn join(mut self, rhs: Self) -> Self {
+        self.0.extend(rhs.0);
+        for (depth, _) in self.0.iter_mut() {
+            *depth += 1;
+        }
+        self
+    }
+
+    // Adds the numbers like `+`, also returning each reduction step along with
+    // the number it left behind.
+    #[allow(dead_code)]
+    fn add_traced(self, rhs: Self) -> (Self, Vec<(Action, FlatNumber)>) {
+        let mut sum = self.join(rhs);
+        let mut trace = Vec::new();
+        while let Some(action) = sum.reduce_step() {
+            trace.push((action, sum.clone()));
+        }
+        (sum, trace)
+    }
+}
+
+impl Add for FlatNumber {
+    type Output = FlatNumber;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        let mut sum = self.join(rhs);
+        while sum.reduce_step().is_some() {}
+        sum
+    }
+}
+
 pub fn star_one(input: impl BufRead) -> usize {
     let mut lines = input.lines().map(|line| {
         let line = line.unwrap();
@@ -171,6 +490,29 @@ pub fn star_two(input: impl BufRead) -> usize {
         .magnitude()
 }
 
+pub fn star_one_flat(input: impl BufRead) -> usize {
+    let mut lines = input.lines().map(|line| {
+        let line = line.unwrap();
+        line.parse::<FlatNumber>().unwrap()
+    });
+    let total = lines.next().unwrap();
+    lines.fold(total, |acc, curr| acc + curr).magnitude()
+}
+
+pub fn star_two_flat(input: impl BufRead) -> usize {
+    let numbers: Vec<FlatNumber> = input
+        .lines()
+        .map(|line| line.unwrap().parse().unwrap())
+        .collect();
+
+    numbers
+        .iter()
+        .permutations(2)
+        .map(|v| (v[0].clone() + v[1].clone()).magnitude())
+        .max()
+        .unwrap()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -348,4 +690,180 @@ mod tests {
 [[[[5,2],5],[8,[3,7]]],[[5,[7,5]],[4,4]]]";
         assert_eq!(star_two(Cursor::new(input)), 3993);
     }
+
+    #[test]
+    fn test_flat_matches_tree() {
+        let lines = [
+            "[[[0,[4,5]],[0,0]],[[[4,5],[2,6]],[9,5]]]",
+            "[7,[[[3,7],[4,3]],[[6,3],[8,8]]]]",
+            "[[2,[[0,8],[3,4]]],[[[6,7],1],[7,[1,6]]]]",
+            "[[[[2,4],7],[6,[0,5]]],[[[6,8],[2,8]],[[2,1],[4,5]]]]",
+            "[7,[5,[[3,8],[1,4]]]]",
+            "[[2,[2,2]],[8,[8,1]]]",
+        ];
+        for line in lines {
+            let flat: FlatNumber = line.parse().unwrap();
+            assert_eq!(flat.to_string(), line);
+        }
+        for (a, b) in lines.iter().tuple_combinations() {
+            let tree = a.parse::<SnailfishNumber>().unwrap() + b.parse().unwrap();
+            let flat = a.parse::<FlatNumber>().unwrap() + b.parse().unwrap();
+            assert_eq!(flat.to_string(), tree.to_string());
+            assert_eq!(flat.magnitude(), tree.magnitude());
+            assert_eq!(flat, FlatNumber::from(&tree));
+        }
+    }
+
+    #[test]
+    fn test_add_traced() {
+        let left: FlatNumber = "[[[[4,3],4],4],[7,[[8,4],9]]]".parse().unwrap();
+        let right: FlatNumber = "[1,1]".parse().unwrap();
+        let (sum, trace) = left.add_traced(right);
+        assert_eq!(sum.to_string(), "[[[[0,7],4],[[7,8],[6,0]]],[8,1]]");
+        let steps: Vec<String> = trace
+            .iter()
+            .map(|(action, after)| format!("{}: {}", action, after))
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                "explode [4,3]: [[[[0,7],4],[7,[[8,4],9]]],[1,1]]",
+                "explode [8,4]: [[[[0,7],4],[15,[0,13]]],[1,1]]",
+                "split 15: [[[[0,7],4],[[7,8],[0,13]]],[1,1]]",
+                "split 13: [[[[0,7],4],[[7,8],[0,[6,7]]]],[1,1]]",
+                "explode [6,7]: [[[[0,7],4],[[7,8],[6,0]]],[8,1]]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_explode_deeper_than_reduced() {
+        let mut number: FlatNumber = "[[[[[1,[2,3]],4],5],6],7]".parse().unwrap();
+        let action = number.reduce_step().unwrap();
+        assert_eq!(action.to_string(), "explode [2,3]");
+        assert_eq!(number.to_string(), "[[[[[3,0],7],5],6],7]");
+        let action = number.reduce_step().unwrap();
+        assert_eq!(action.to_string(), "explode [3,0]");
+        assert_eq!(number.to_string(), "[[[[0,7],5],6],7]");
+        assert!(number.reduce_step().is_none());
+    }
+
+    #[test]
+    fn test_flat_stars() {
+        let input = b"[[[0,[5,8]],[[1,7],[9,6]]],[[4,[1,2]],[[1,4],2]]]
+[[[5,[2,8]],4],[5,[[9,9],0]]]
+[6,[[[6,2],[5,6]],[[7,6],[4,7]]]]
+[[[6,[0,7]],[0,9]],[4,[9,[9,0]]]]
+[[[7,[6,4]],[3,[1,3]]],[[[5,5],1],9]]
+[[6,[[7,3],[3,2]]],[[[3,8],[5,7]],4]]
+[[[[5,4],[7,7]],8],[[8,3],8]]
+[[9,3],[[9,9],[6,[4,9]]]]
+[[2,[[7,7],7]],[[5,8],[[9,3],[0,2]]]]
+[[[[5,2],5],[8,[3,7]]],[[5,[7,5]],[4,4]]]";
+        assert_eq!(star_one_flat(Cursor::new(input)), 4140);
+        assert_eq!(star_two_flat(Cursor::new(input)), 3993);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse = |s: &str| s.parse::<SnailfishNumber>().unwrap_err();
+        assert_eq!(parse("[1,2"), ParseError::Unclosed { position: 0 });
+        assert_eq!(parse("[[1,2],[3,4]"), ParseError::Unclosed { position: 0 });
+        assert_eq!(parse("[1,2]]"), ParseError::Unmatched { position: 5 });
+        assert_eq!(
+            parse("[1,2,3]"),
+            ParseError::Unexpected {
+                position: 4,
+                found: ',',
+                expected: "']'"
+            }
+        );
+        assert_eq!(
+            parse("[1, 2]"),
+            ParseError::Unexpected {
+                position: 3,
+                found: ' ',
+                expected: "a number or '['"
+            }
+        );
+        assert_eq!(
+            parse("[[1,2]x"),
+            ParseError::Unexpected {
+                position: 6,
+                found: 'x',
+                expected: "','"
+            }
+        );
+        assert_eq!(parse("[1,"), ParseError::UnexpectedEnd { position: 3 });
+        assert_eq!(
+            parse("[[1,2],\u{e9}]"),
+            ParseError::Unexpected {
+                position: 7,
+                found: '\u{e9}',
+                expected: "a number or '['"
+            }
+        );
+        assert_eq!(
+            parse("7"),
+            ParseError::Unexpected {
+                position: 0,
+                found: '7',
+                expected: "'['"
+            }
+        );
+        assert_eq!(
+            parse("[1,99999999999999999999999]"),
+            ParseError::ValueTooLarge { position: 3 }
+        );
+        assert_eq!(
+            parse("[1,2]]").to_string(),
+            "Unmatched ']' at 5".to_string()
+        );
+
+        // Unreduced numbers still parse.
+        assert_eq!(
+            "[10,[[[[1,2],3],4],5]]"
+                .parse::<SnailfishNumber>()
+                .map(|n| n.to_string()),
+            Ok("[10,[[[[1,2],3],4],5]]".to_string())
+        );
+    }
+
+    #[test]
+    fn test_check_homework() {
+        let homework = b"[[[0,[5,8]],[[1,7],[9,6]]],[[4,[1,2]],[[1,4],2]]]
+[[[[[9,8],1],2],3],4]
+[12,[3,[[[1,2],4],5]]]
+[1,2
+[[1,2],[3,4]]";
+        assert_eq!(
+            check_homework(Cursor::new(homework)),
+            vec![
+                (1, Problem::TooDeep { position: 4 }),
+                (
+                    2,
+                    Problem::TooLarge {
+                        position: 1,
+                        value: 12
+                    }
+                ),
+                (2, Problem::TooDeep { position: 9 }),
+                (3, Problem::Invalid(ParseError::Unclosed { position: 0 })),
+            ]
+        );
+        // Columns come from the parse, so leading zeros do not throw them.
+        assert_eq!(
+            check_homework(Cursor::new("[[[[[01,2],3],4],5],[010,1]]")),
+            vec![
+                (0, Problem::TooDeep { position: 4 }),
+                (
+                    0,
+                    Problem::TooLarge {
+                        position: 21,
+                        value: 10
+                    }
+                ),
+            ]
+        );
+    }
 }
@@ -1,4 +1,9 @@
-use std::{collections::HashSet, fmt::Display, io::BufRead, str::FromStr};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    fmt::Display,
+    io::BufRead,
+    str::FromStr,
+};
 
 use itertools::Itertools;
 use nalgebra::{Matrix3, Point3, Vector3};
@@ -74,299 +79,424 @@ impl FromStr for Scanner {
     }
 }
 
-fn distance_from_to(beacons: &[Beacon]) -> Vec<(Beacon, HashSet<usize>)> {
-    beacons
-        .iter()
-        .map(|p1| {
-            let distances: HashSet<usize> = beacons.iter().map(|p2| mh_distance(p1, p2)).collect();
-            (*p1, distances)
-        })
-        .collect()
+// Takes a scanner's own coordinates to those of the first scanner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Placement {
+    rotation: Matrix3<isize>,
+    translation: Vector3<isize>,
 }
 
-fn get_matching_points(
-    scanners: &[Scanner],
-    positioned_distances: &[(Beacon, HashSet<usize>)],
-    threshold: usize,
-) -> Option<(usize, Beacon, Beacon, usize)> {
-    for (reference_point, reference_distances) in positioned_distances {
-        for (i, scanner) in scanners.iter().enumerate() {
-            let distances = distance_from_to(&scanner.beacons);
-            for (j, (scanner_point, beacon_distances)) in distances.into_iter().enumerate() {
-                let count = reference_distances.intersection(&beacon_distances).count();
-                if count >= threshold {
-                    return Some((i, *reference_point, scanner_point, j));
-                }
-            }
+impl Placement {
+    fn identity() -> Self {
+        Placement {
+            rotation: Matrix3::identity(),
+            translation: Vector3::zeros(),
         }
     }
-    None
-}
 
-fn get_orientation(
-    positioned_beacons: &HashSet<Beacon>,
-    ref_point: &Beacon,
-    scanner: &Scanner,
-    scanner_point_i: usize,
-    threshold: usize,
-) -> Option<(HashSet<Beacon>, Vector3<isize>)> {
-    for rotation in get_rotations() {
-        let rotated_point = rotation * scanner.beacons[scanner_point_i];
-
-        let translation = ref_point - rotated_point;
-        assert_eq!(
-            &(rotation * scanner.beacons[scanner_point_i] + translation),
-            ref_point
-        );
+    fn apply(&self, beacon: &Beacon) -> Beacon {
+        self.rotation * beacon + self.translation
+    }
 
-        let translated_beacons: HashSet<Beacon> = scanner
-            .beacons
-            .iter()
-            .map(|b| rotation * b + translation)
-            .collect();
+    // Applies `other` first and then `self`.
+    fn compose(&self, other: &Placement) -> Placement {
+        Placement {
+            rotation: self.rotation * other.rotation,
+            translation: self.rotation * other.translation + self.translation,
+        }
+    }
 
-        if positioned_beacons.intersection(&translated_beacons).count() >= threshold {
-            // println!("Found rotation for scanner {}", scanner.number);
-            // println!("{:?}", translated_beacons);
-            return Some((translated_beacons, -translation));
+    fn inverse(&self) -> Placement {
+        let rotation = self.rotation.transpose();
+        Placement {
+            rotation,
+            translation: -(rotation * self.translation),
         }
-        // println!("\n\n");
     }
-    // println!("No match for scanner {} using ", scanner.number);
-    None
 }
 
-fn process(mut input: impl BufRead, threshold: usize) -> (HashSet<Beacon>, Vec<Vector3<isize>>) {
-    let mut buf = String::new();
-    input.read_to_string(&mut buf).unwrap();
-    let mut scanners = buf
-        .split("\n\n")
-        .map(|scanner_section| scanner_section.parse::<Scanner>().unwrap())
-        .collect::<Vec<_>>();
+fn squared_distance(a: &Beacon, b: &Beacon) -> isize {
+    (a - b).dot(&(a - b))
+}
 
-    assert_eq!(get_rotations().count(), 24);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct AlignOptions {
+    // How many beacons two scanners have to both see to count as overlapping.
+    min_overlap: usize,
+    // How far, along each axis, a reported coordinate may be off.
+    tolerance: isize,
+}
 
-    let mut scanner_positions = vec![Vector3::from([0, 0, 0])];
-    let mut positioned_beacons: HashSet<_> = scanners.remove(0).beacons.iter().cloned().collect();
+impl Default for AlignOptions {
+    fn default() -> Self {
+        AlignOptions {
+            min_overlap: 12,
+            tolerance: 0,
+        }
+    }
+}
 
-    while !scanners.is_empty() {
-        // Get all the distances between points that are positioned
-        let v: Vec<_> = positioned_beacons.iter().cloned().collect();
-        let distances = distance_from_to(&v);
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AlignError {
+    // Numbers of the scanners with no chain of overlaps to the first one.
+    unplaced: Vec<usize>,
+}
 
-        // Get a positioned point and a point from another scanner that has the same distances to at least 12 other points.
-        // Assume these two points are the same beacon
-        // and translate/rotate the beacons of the scanner to the translation from two points.
-        let (i, ref_point, _scanner_point, scanner_point_i) =
-            get_matching_points(&scanners, &distances, threshold)
-                .unwrap_or_else(|| panic!("Shit went wrong: {}\n", scanners.len(),));
+impl Display for AlignError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Could not place scanners {}", join(&self.unplaced))
+    }
+}
 
-        let scanner = scanners.remove(i);
+// Every squared distance between two beacons of the same scanner, with the
+// scanner and the beacon pair it was seen between, sorted by distance.
+// Distances do not change when a scanner is rotated or moved, so scanners
+// that see the same beacons share the distances between them.
+fn fingerprints(scanners: &[Scanner]) -> Vec<(isize, usize, usize, usize)> {
+    let mut distances = Vec::new();
+    for (s, scanner) in scanners.iter().enumerate() {
+        for ((i, a), (j, b)) in scanner.beacons.iter().enumerate().tuple_combinations() {
+            distances.push((squared_distance(a, b), s, i, j));
+        }
+    }
+    distances.sort_unstable();
+    distances
+}
 
-        let (translated_beacons, translation) = get_orientation(
-            &positioned_beacons,
-            &ref_point,
-            &scanner,
-            scanner_point_i,
-            threshold,
-        )
-        .unwrap();
-        positioned_beacons.extend(translated_beacons);
-        scanner_positions.push(translation);
+// A beacon pair in one scanner and one in another the same distance apart.
+type SharedPair = ((usize, usize), (usize, usize));
+
+// Pairs of scanners sharing enough distances to possibly see `min_overlap`
+// of the same beacons, with the beacon pairs behind each shared distance.
+// Two noisy readings of the same distance can differ by up to four times the
+// tolerance along each axis.
+fn candidate_pairs(
+    distances: &[(isize, usize, usize, usize)],
+    options: &AlignOptions,
+) -> BTreeMap<(usize, usize), Vec<SharedPair>> {
+    let slack = 4.0 * 3f64.sqrt() * options.tolerance as f64;
+    let mut shared: BTreeMap<(usize, usize), Vec<_>> = BTreeMap::new();
+    for (k, &(d, s, a1, a2)) in distances.iter().enumerate() {
+        for &(e, t, b1, b2) in &distances[k + 1..] {
+            if e != d && (e as f64).sqrt() - (d as f64).sqrt() > slack {
+                break;
+            }
+            if s != t {
+                let key = (s.min(t), s.max(t));
+                let pair = if s < t {
+                    ((a1, a2), (b1, b2))
+                } else {
+                    ((b1, b2), (a1, a2))
+                };
+                shared.entry(key).or_default().push(pair);
+            }
+        }
     }
-    (positioned_beacons, scanner_positions)
+    let needed = options.min_overlap * options.min_overlap.saturating_sub(1) / 2;
+    shared.retain(|_, pairs| pairs.len() >= needed);
+    shared
 }
 
-pub fn star_one(input: impl BufRead) -> usize {
-    process(input, 12).0.len()
+fn median(mut values: Vec<isize>) -> isize {
+    values.sort_unstable();
+    values[values.len() / 2]
 }
 
-pub fn star_two(input: impl BufRead) -> usize {
-    process(input, 12)
-        .1
-        .iter()
-        .combinations(2)
-        .max_by_key(|a| {
-            a[0].iter()
-                .zip(a[1].iter())
-                .map(|(c1, c2)| (c1 - c2).unsigned_abs())
-                .sum::<usize>()
-        })
-        .map(|v| {
-            v[0].iter()
-                .zip(v[1].iter())
-                .map(|(c1, c2)| (c1 - c2).unsigned_abs())
-                .sum()
+// Works out where scanner `t` sits relative to scanner `s` from the shared
+// distances between them. Each shared distance votes for its two beacons in
+// `s` being its two beacons in `t`, in either order; beacons seen by both
+// scanners collect a vote from every other beacon they both see. The
+// rotation is then the one that lines up the most voted for beacons, with
+// the median translation between them. Noise in both readings and in that
+// translation can leave a beacon up to four times the tolerance out.
+fn relative_placement(
+    s: &Scanner,
+    t: &Scanner,
+    shared: &[SharedPair],
+    options: &AlignOptions,
+) -> Option<Placement> {
+    let mut votes: HashMap<(usize, usize), usize> = HashMap::new();
+    for &((a1, a2), (b1, b2)) in shared {
+        for pair in [(a1, b1), (a1, b2), (a2, b1), (a2, b2)] {
+            *votes.entry(pair).or_default() += 1;
+        }
+    }
+    let matches: Vec<(Beacon, Beacon)> = votes
+        .into_iter()
+        .filter(|&(_, count)| count + 1 >= options.min_overlap)
+        .map(|((a, b), _)| (s.beacons[a], t.beacons[b]))
+        .collect();
+    if matches.is_empty() {
+        return None;
+    }
+
+    get_rotations()
+        .map(|rotation| {
+            let offsets: Vec<Vector3<isize>> =
+                matches.iter().map(|(a, b)| a - rotation * b).collect();
+            let translation = Vector3::from_fn(|axis, _| {
+                median(offsets.iter().map(|offset| offset[axis]).collect())
+            });
+            let placement = Placement {
+                rotation,
+                translation,
+            };
+            let lined_up = matches
+                .iter()
+                .filter(|(a, b)| (placement.apply(b) - a).abs().max() <= 4 * options.tolerance)
+                .count();
+            (lined_up, placement)
         })
-        .unwrap()
+        .filter(|&(lined_up, _)| lined_up >= options.min_overlap)
+        .max_by_key(|&(lined_up, _)| lined_up)
+        .map(|(_, placement)| placement)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Cursor;
+// Drops every point within `radius` along each axis of one already kept, by
+// bucketing the kept points into cells at least `radius` wide.
+fn merge_points(points: impl Iterator<Item = Beacon>, radius: isize) -> Vec<Beacon> {
+    let cell = radius.max(1);
+    let mut grid: HashMap<Vector3<isize>, Vec<Beacon>> = HashMap::new();
+    let mut kept = Vec::new();
+    for point in points {
+        let key = point.coords.map(|c| c.div_euclid(cell));
+        let near = (-1..=1)
+            .cartesian_product(-1..=1)
+            .cartesian_product(-1..=1)
+            .any(|((dx, dy), dz)| {
+                grid.get(&(key + Vector3::new(dx, dy, dz)))
+                    .is_some_and(|cell| cell.iter().any(|p| (p - point).abs().max() <= radius))
+            });
+        if !near {
+            grid.entry(key).or_default().push(point);
+            kept.push(point);
+        }
+    }
+    kept
+}
 
-    #[test]
-    fn test_orientation() {
-        let scanner1: Scanner = "--- scanner 0 ---
-404,-588,-901
-528,-643,409
--838,591,734
-390,-675,-793
--537,-823,-458
--485,-357,347
--345,-311,381
--661,-816,-575
--876,649,763
--618,-824,-621
-553,345,-567
-474,580,667
--447,-329,318
--584,868,-557
-544,-627,-890
-564,392,-477
-455,729,728
--892,524,684
--689,845,-530
-423,-701,434
-7,-33,-71
-630,319,-379
-443,580,662
--789,900,-551
-459,-707,401
-"
-        .parse()
-        .unwrap();
+// Where every scanner sits relative to the first one, which pairs of
+// scanners see enough of the same beacons, the (parent, child) overlaps used
+// to place each scanner, and every beacon seen, sorted by coordinates.
+#[derive(Debug, Clone)]
+struct Alignment {
+    numbers: Vec<usize>,
+    placements: Vec<Placement>,
+    overlaps: BTreeSet<(usize, usize)>,
+    tree: Vec<(usize, usize)>,
+    beacons: Vec<Beacon>,
+}
 
-        let scanner2: Scanner = "--- scanner 1 ---
-686,422,578
-605,423,415
-515,917,-361
--336,658,858
-95,138,22
--476,619,847
--340,-569,-846
-567,-361,727
--460,603,-452
-669,-402,600
-729,430,532
--500,-761,534
--322,571,750
--466,-666,-811
--429,-592,574
--355,545,-477
-703,-491,-529
--328,-685,520
-413,935,-424
--391,539,-444
-586,-435,557
--364,-763,-893
-807,-499,-711
-755,-354,-619
-553,889,-390
-"
-        .parse()
-        .unwrap();
-        let beacons = scanner1.beacons.iter().cloned().collect();
-        let expected = HashSet::from([
-            Point3::from([534, -1912, 768]),
-            Point3::from([432, -2009, 850]),
-            Point3::from([459, -707, 401]),
-            Point3::from([390, -675, -793]),
-            Point3::from([404, -588, -901]),
-            Point3::from([528, -643, 409]),
-            Point3::from([497, -1838, -617]),
-            Point3::from([-537, -823, -458]),
-            Point3::from([568, -2007, -577]),
-            Point3::from([-447, -329, 318]),
-            Point3::from([-687, -1600, 576]),
-            Point3::from([-27, -1108, -65]),
-            Point3::from([-601, -1648, -643]),
-            Point3::from([-518, -1681, -600]),
-            Point3::from([408, -1815, 803]),
-            Point3::from([-661, -816, -575]),
-            Point3::from([-345, -311, 381]),
-            Point3::from([-618, -824, -621]),
-            Point3::from([-485, -357, 347]),
-            Point3::from([423, -701, 434]),
-            Point3::from([-635, -1737, 486]),
-            Point3::from([-499, -1607, -770]),
-            Point3::from([-739, -1745, 668]),
-            Point3::from([544, -627, -890]),
-            Point3::from([396, -1931, -563]),
-        ]);
-        // for i in 0..scanner2.beacons.len() {
-        //     let r = get_orientation(&beacons, &scanner1.beacons[9], &scanner2, i, 12);
-        //     if r.is_some() {
-        //         panic!("Blah{i}");
-        //     }
-        // }
-        assert_eq!(
-            get_orientation(&beacons, &scanner1.beacons[9], &scanner2, 0, 12)
-                .unwrap()
-                .0,
-            expected
-        );
+fn parse_input(mut input: impl BufRead) -> Vec<Scanner> {
+    let mut buf = String::new();
+    input.read_to_string(&mut buf).unwrap();
+    buf.split("\n\n")
+        .map(|scanner_section| scanner_section.parse::<Scanner>().unwrap())
+        .collect()
+}
+
+// Finds every overlapping pair of scanners through their fingerprints, then
+// places the scanners by walking a spanning tree of those overlaps out from
+// the first scanner. Beacons seen by several scanners are merged when they
+// land within four times the tolerance of each other.
+fn align(scanners: &[Scanner], options: &AlignOptions) -> Result<Alignment, AlignError> {
+    let distances = fingerprints(scanners);
+    let mut edges: Vec<Vec<(usize, Placement)>> = vec![Vec::new(); scanners.len()];
+    let mut overlaps = BTreeSet::new();
+    for ((s, t), shared) in candidate_pairs(&distances, options) {
+        if let Some(placement) = relative_placement(&scanners[s], &scanners[t], &shared, options) {
+            edges[s].push((t, placement));
+            edges[t].push((s, placement.inverse()));
+            overlaps.insert((s, t));
+        }
     }
 
-    #[test]
-    fn test_orientations() {
-        let scanner0 = "--- scanner 0 ---
-0,2,0
-4,1,0
-3,3,0"
-            .parse::<Scanner>()
-            .unwrap();
+    let mut placements = vec![None; scanners.len()];
+    let mut tree = Vec::new();
+    let mut order = Vec::new();
+    let mut queue = VecDeque::new();
+    if !scanners.is_empty() {
+        placements[0] = Some(Placement::identity());
+        queue.push_back(0);
+    }
+    while let Some(s) = queue.pop_front() {
+        order.push(s);
+        let placement = placements[s].unwrap();
+        for &(t, relative) in &edges[s] {
+            if placements[t].is_none() {
+                placements[t] = Some(placement.compose(&relative));
+                tree.push((s, t));
+                queue.push_back(t);
+            }
+        }
+    }
 
-        let beacons = scanner0.beacons.iter().cloned().collect();
+    let unplaced: Vec<usize> = placements
+        .iter()
+        .zip(scanners)
+        .filter(|(p, _)| p.is_none())
+        .map(|(_, scanner)| scanner.number)
+        .collect();
+    if !unplaced.is_empty() {
+        return Err(AlignError { unplaced });
+    }
+    let placements: Vec<Placement> = placements.into_iter().map(Option::unwrap).collect();
+    let beacons = merge_points(
+        order.iter().flat_map(|&s| {
+            let placement = placements[s];
+            scanners[s].beacons.iter().map(move |b| placement.apply(b))
+        }),
+        4 * options.tolerance,
+    );
+
+    Ok(Alignment {
+        numbers: scanners.iter().map(|s| s.number).collect(),
+        placements,
+        overlaps,
+        tree,
+        beacons: beacons
+            .into_iter()
+            .sorted_by_key(|b| (b.x, b.y, b.z))
+            .collect(),
+    })
+}
 
-        let scanner1: Scanner = "--- scanner 1 ---
--1,-1,0
--5,0,0
--2,1,0"
-            .parse()
-            .unwrap();
+fn join(values: impl IntoIterator<Item = impl Display>) -> String {
+    values.into_iter().map(|v| v.to_string()).join(",")
+}
 
-        assert_eq!(
-            get_orientation(&beacons, &scanner0.beacons[1], &scanner1, 0, 2)
-                .unwrap()
-                .0,
-            HashSet::from([
-                Point3::from([0, 2, 0]),
-                Point3::from([4, 1, 0]),
-                Point3::from([3, 3, 0]),
-            ])
+impl Alignment {
+    fn positions(&self) -> impl Iterator<Item = Beacon> + Clone + '_ {
+        self.placements.iter().map(|p| Point3::from(p.translation))
+    }
+
+    fn max_scanner_distance(&self) -> usize {
+        self.positions()
+            .tuple_combinations()
+            .map(|(a, b)| mh_distance(&a, &b))
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn to_json(&self) -> String {
+        let point = |v: &[isize]| format!("[{}]", join(v));
+        let scanners = self
+            .numbers
+            .iter()
+            .zip(&self.placements)
+            .map(|(number, p)| {
+                let rows = p
+                    .rotation
+                    .row_iter()
+                    .map(|row| point(&[row[0], row[1], row[2]]));
+                format!(
+                    "{{\"number\":{},\"rotation\":[{}],\"translation\":{}}}",
+                    number,
+                    join(rows),
+                    point(p.translation.as_slice())
+                )
+            });
+        let overlaps = self
+            .overlaps
+            .iter()
+            .map(|&(a, b)| point(&[self.numbers[a] as isize, self.numbers[b] as isize]));
+        let tree = self
+            .tree
+            .iter()
+            .map(|&(a, b)| point(&[self.numbers[a] as isize, self.numbers[b] as isize]));
+        let beacons = self.beacons.iter().map(|b| point(b.coords.as_slice()));
+        format!(
+            "{{\"scanners\":[{}],\"overlaps\":[{}],\"tree\":[{}],\"beacons\":[{}]}}",
+            join(scanners),
+            join(overlaps),
+            join(tree),
+            join(beacons)
+        )
+    }
+
+    // Scanners come first, in red, followed by the beacons in white. Each
+    // overlapping pair of scanners is joined by an edge.
+    fn to_ply(&self) -> String {
+        let mut ply = String::new();
+        ply += "ply\nformat ascii 1.0\n";
+        ply += &format!(
+            "element vertex {}\n",
+            self.placements.len() + self.beacons.len()
         );
+        ply += "property int x\nproperty int y\nproperty int z\n";
+        ply += "property uchar red\nproperty uchar green\nproperty uchar blue\n";
+        ply += &format!("element edge {}\n", self.overlaps.len());
+        ply += "property int vertex1\nproperty int vertex2\nend_header\n";
+        for p in self.positions() {
+            ply += &format!("{} {} {} 255 0 0\n", p.x, p.y, p.z);
+        }
+        for b in &self.beacons {
+            ply += &format!("{} {} {} 255 255 255\n", b.x, b.y, b.z);
+        }
+        for (a, b) in &self.overlaps {
+            ply += &format!("{} {}\n", a, b);
+        }
+        ply
     }
 
-    #[ignore]
-    #[test]
-    fn test_star_one_rot_trans() {
-        let v = Point3::from([1, 2, 3]);
-        for rotation in get_rotations() {
-            println!("{}: {}", rotation, rotation * v);
+    // The same layout as `to_ply`, with the scanners and beacons as separate
+    // objects.
+    fn to_obj(&self) -> String {
+        let mut obj = String::from("o scanners\n");
+        for (number, p) in self.numbers.iter().zip(self.positions()) {
+            obj += &format!("# scanner {}\nv {} {} {}\n", number, p.x, p.y, p.z);
+        }
+        for (a, b) in &self.overlaps {
+            obj += &format!("l {} {}\n", a + 1, b + 1);
         }
+        obj += "o beacons\n";
+        for b in &self.beacons {
+            obj += &format!("v {} {} {}\n", b.x, b.y, b.z);
+        }
+        obj
     }
+}
 
-    #[test]
-    fn test_star_one_2d() {
-        let input = b"--- scanner 0 ---
-0,2,0
-4,1,0
-3,3,0
+// Aligns the scanners and writes the result for a 3D viewer, as "json",
+// "ply" or "obj".
+pub fn export(input: impl BufRead, format: &str) -> Result<String, String> {
+    let alignment =
+        align(&parse_input(input), &AlignOptions::default()).map_err(|e| e.to_string())?;
+    match format {
+        "json" => Ok(alignment.to_json()),
+        "ply" => Ok(alignment.to_ply()),
+        "obj" => Ok(alignment.to_obj()),
+        _ => Err(format!("Unknown export format {:?}", format)),
+    }
+}
 
---- scanner 1 ---
--1,-1,0
--5,0,0
--2,1,0
-";
+pub fn star_one(input: impl BufRead) -> usize {
+    align(&parse_input(input), &AlignOptions::default())
+        .unwrap()
+        .beacons
+        .len()
+}
+
+pub fn star_two(input: impl BufRead) -> usize {
+    align(&parse_input(input), &AlignOptions::default())
+        .unwrap()
+        .max_scanner_distance()
+}
 
-        assert_eq!(process(Cursor::new(input), 3).0.len(), 3);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Random;
+    use std::{collections::HashSet, io::Cursor};
+
+    fn overlap(min_overlap: usize) -> AlignOptions {
+        AlignOptions {
+            min_overlap,
+            tolerance: 0,
+        }
     }
 
-    #[test]
-    fn test_star_one() {
-        let input = b"--- scanner 0 ---
+    const INPUT: &[u8] = b"--- scanner 0 ---
 404,-588,-901
 528,-643,409
 -838,591,734
@@ -503,148 +633,313 @@ mod tests {
 -652,-548,-490
 30,-46,-14
 ";
-        assert_eq!(star_one(Cursor::new(input)), 79);
+
+    #[test]
+    fn test_relative_placement() {
+        let scanners = parse_input(Cursor::new(INPUT));
+        let options = AlignOptions::default();
+        let pairs = candidate_pairs(&fingerprints(&scanners[..2]), &options);
+        let placement =
+            relative_placement(&scanners[0], &scanners[1], &pairs[&(0, 1)], &options).unwrap();
+        let expected = HashSet::from([
+            Point3::from([534, -1912, 768]),
+            Point3::from([432, -2009, 850]),
+            Point3::from([459, -707, 401]),
+            Point3::from([390, -675, -793]),
+            Point3::from([404, -588, -901]),
+            Point3::from([528, -643, 409]),
+            Point3::from([497, -1838, -617]),
+            Point3::from([-537, -823, -458]),
+            Point3::from([568, -2007, -577]),
+            Point3::from([-447, -329, 318]),
+            Point3::from([-687, -1600, 576]),
+            Point3::from([-27, -1108, -65]),
+            Point3::from([-601, -1648, -643]),
+            Point3::from([-518, -1681, -600]),
+            Point3::from([408, -1815, 803]),
+            Point3::from([-661, -816, -575]),
+            Point3::from([-345, -311, 381]),
+            Point3::from([-618, -824, -621]),
+            Point3::from([-485, -357, 347]),
+            Point3::from([423, -701, 434]),
+            Point3::from([-635, -1737, 486]),
+            Point3::from([-499, -1607, -770]),
+            Point3::from([-739, -1745, 668]),
+            Point3::from([544, -627, -890]),
+            Point3::from([396, -1931, -563]),
+        ]);
+        let placed: HashSet<Beacon> = scanners[1]
+            .beacons
+            .iter()
+            .map(|b| placement.apply(b))
+            .collect();
+        assert_eq!(placed, expected);
+    }
+
+    #[test]
+    fn test_relative_placement_2d() {
+        let scanners = [
+            "--- scanner 0 ---\n0,2,0\n4,1,0\n3,3,0",
+            "--- scanner 1 ---\n-1,-1,0\n-5,0,0\n-2,1,0",
+        ]
+        .map(|s| s.parse::<Scanner>().unwrap());
+        let options = AlignOptions {
+            min_overlap: 3,
+            tolerance: 0,
+        };
+        let pairs = candidate_pairs(&fingerprints(&scanners), &options);
+        let placement =
+            relative_placement(&scanners[0], &scanners[1], &pairs[&(0, 1)], &options).unwrap();
+        let placed: HashSet<Beacon> = scanners[1]
+            .beacons
+            .iter()
+            .map(|b| placement.apply(b))
+            .collect();
+        assert_eq!(
+            placed,
+            HashSet::from([
+                Point3::from([0, 2, 0]),
+                Point3::from([4, 1, 0]),
+                Point3::from([3, 3, 0]),
+            ])
+        );
+    }
+
+    #[ignore]
+    #[test]
+    fn test_star_one_rot_trans() {
+        let v = Point3::from([1, 2, 3]);
+        for rotation in get_rotations() {
+            println!("{}: {}", rotation, rotation * v);
+        }
+    }
+
+    #[test]
+    fn test_star_one_2d() {
+        let input = b"--- scanner 0 ---
+0,2,0
+4,1,0
+3,3,0
+
+--- scanner 1 ---
+-1,-1,0
+-5,0,0
+-2,1,0
+";
+
+        assert_eq!(
+            align(&parse_input(Cursor::new(input)), &overlap(3))
+                .unwrap()
+                .beacons
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_star_one() {
+        assert_eq!(star_one(Cursor::new(INPUT)), 79);
     }
 
     #[test]
     fn test_star_two() {
+        assert_eq!(star_two(Cursor::new(INPUT)), 3621);
+    }
+
+    #[test]
+    fn test_alignment() {
+        let alignment = align(&parse_input(Cursor::new(INPUT)), &AlignOptions::default()).unwrap();
+        let positions: Vec<Beacon> = alignment.positions().collect();
+        assert_eq!(
+            positions,
+            [
+                Point3::from([0, 0, 0]),
+                Point3::from([68, -1246, -43]),
+                Point3::from([1105, -1205, 1229]),
+                Point3::from([-92, -2380, -20]),
+                Point3::from([-20, -1133, 1061]),
+            ]
+        );
+        assert_eq!(
+            alignment.overlaps,
+            BTreeSet::from([(0, 1), (1, 3), (1, 4), (2, 4)])
+        );
+        assert_eq!(
+            alignment.placements[1].apply(&Point3::from([686, 422, 578])),
+            Point3::from([-618, -824, -621])
+        );
+    }
+
+    #[test]
+    fn test_export() {
         let input = b"--- scanner 0 ---
-404,-588,-901
-528,-643,409
--838,591,734
-390,-675,-793
--537,-823,-458
--485,-357,347
--345,-311,381
--661,-816,-575
--876,649,763
--618,-824,-621
-553,345,-567
-474,580,667
--447,-329,318
--584,868,-557
-544,-627,-890
-564,392,-477
-455,729,728
--892,524,684
--689,845,-530
-423,-701,434
-7,-33,-71
-630,319,-379
-443,580,662
--789,900,-551
-459,-707,401
+0,2,0
+4,1,0
+3,3,0
 
 --- scanner 1 ---
-686,422,578
-605,423,415
-515,917,-361
--336,658,858
-95,138,22
--476,619,847
--340,-569,-846
-567,-361,727
--460,603,-452
-669,-402,600
-729,430,532
--500,-761,534
--322,571,750
--466,-666,-811
--429,-592,574
--355,545,-477
-703,-491,-529
--328,-685,520
-413,935,-424
--391,539,-444
-586,-435,557
--364,-763,-893
-807,-499,-711
-755,-354,-619
-553,889,-390
+-1,-1,0
+-5,0,0
+-2,1,0
+";
+        let alignment = align(&parse_input(Cursor::new(input)), &overlap(3)).unwrap();
+        assert_eq!(
+            alignment.to_json(),
+            concat!(
+                r#"{"scanners":[{"number":0,"rotation":[[1,0,0],[0,1,0],[0,0,1]],"translation":[0,0,0]},"#,
+                r#"{"number":1,"rotation":[[1,0,0],[0,1,0],[0,0,1]],"translation":[5,2,0]}],"#,
+                r#""overlaps":[[0,1]],"tree":[[0,1]],"beacons":[[0,2,0],[3,3,0],[4,1,0]]}"#
+            )
+        );
+        assert_eq!(
+            alignment.to_obj(),
+            "o scanners\n# scanner 0\nv 0 0 0\n# scanner 1\nv 5 2 0\nl 1 2\n\
+             o beacons\nv 0 2 0\nv 3 3 0\nv 4 1 0\n"
+        );
 
---- scanner 2 ---
-649,640,665
-682,-795,504
--784,533,-524
--644,584,-595
--588,-843,648
--30,6,44
--674,560,763
-500,723,-460
-609,671,-379
--555,-800,653
--675,-892,-343
-697,-426,-610
-578,704,681
-493,664,-388
--671,-858,530
--667,343,800
-571,-461,-707
--138,-166,112
--889,563,-600
-646,-828,498
-640,759,510
--630,509,768
--681,-892,-333
-673,-379,-804
--742,-814,-386
-577,-820,562
+        let alignment = align(&parse_input(Cursor::new(INPUT)), &AlignOptions::default()).unwrap();
+        let ply = alignment.to_ply();
+        assert!(ply.starts_with("ply\nformat ascii 1.0\nelement vertex 84\n"));
+        assert!(ply.contains("element edge 4\n"));
+        let body = ply.split_once("end_header\n").unwrap().1;
+        assert_eq!(body.lines().count(), 84 + 4);
+        assert_eq!(body.lines().nth(1), Some("68 -1246 -43 255 0 0"));
 
---- scanner 3 ---
--589,542,597
-605,-692,669
--500,565,-823
--660,373,557
--458,-679,-417
--488,449,543
--626,468,-788
-338,-750,-386
-528,-832,-391
-562,-778,733
--938,-730,414
-543,643,-506
--524,371,-870
-407,773,750
--104,29,83
-378,-903,-323
--778,-728,485
-426,699,580
--438,-605,-362
--469,-447,-387
-509,732,623
-647,635,-688
--868,-804,481
-614,-800,639
-595,780,-596
+        assert_eq!(export(Cursor::new(INPUT), "ply"), Ok(ply));
+        assert_eq!(export(Cursor::new(INPUT), "obj"), Ok(alignment.to_obj()));
+        assert_eq!(
+            export(Cursor::new(INPUT), "stl"),
+            Err("Unknown export format \"stl\"".to_string())
+        );
+    }
 
---- scanner 4 ---
-727,592,562
--293,-554,779
-441,611,-461
--714,465,-776
--743,427,-804
--660,-479,-426
-832,-632,460
-927,-485,-438
-408,393,-506
-466,436,-512
-110,16,151
--258,-428,682
--393,719,612
--211,-452,876
-808,-476,-593
--575,615,604
--485,667,467
--680,325,-822
--627,-443,-432
-872,-547,-609
-833,512,582
-807,604,487
-839,-516,451
-891,-625,532
--652,-548,-490
-30,-46,-14
-";
-        assert_eq!(star_two(Cursor::new(input)), 3621);
+    #[test]
+    fn test_spanning_tree() {
+        let alignment = align(&parse_input(Cursor::new(INPUT)), &AlignOptions::default()).unwrap();
+        assert_eq!(alignment.tree.len(), 4);
+        for &(parent, child) in &alignment.tree {
+            assert!(alignment
+                .overlaps
+                .contains(&(parent.min(child), parent.max(child))));
+        }
+    }
+
+    // A chain of scanners, each seeing the beacons of the chunks either side
+    // of it, with random rotations and positions and every reported
+    // coordinate off by up to `noise`. Returns the scanners and where they
+    // really are.
+    fn generate_chain(count: usize, noise: isize) -> (Vec<Scanner>, Vec<Placement>) {
+        let mut random = Random(7);
+        let rotations: Vec<Matrix3<isize>> = get_rotations().collect();
+        let chunks: Vec<Vec<Beacon>> = (0..count + 1)
+            .map(|_| {
+                (0..13)
+                    .map(|_| {
+                        Point3::from([
+                            random.range(1_000_000),
+                            random.range(1_000_000),
+                            random.range(1_000_000),
+                        ])
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut truth = Vec::new();
+        let mut scanners = Vec::new();
+        for number in 0..count {
+            let placement = if number == 0 {
+                Placement::identity()
+            } else {
+                Placement {
+                    rotation: rotations[(random.range(11) + 12) as usize],
+                    translation: Vector3::from([
+                        random.range(5000),
+                        random.range(5000),
+                        random.range(5000),
+                    ]),
+                }
+            };
+            let inverse = placement.inverse();
+            let beacons = chunks[number]
+                .iter()
+                .chain(&chunks[number + 1])
+                .map(|b| {
+                    inverse.apply(b)
+                        + Vector3::from([
+                            random.range(noise),
+                            random.range(noise),
+                            random.range(noise),
+                        ])
+                })
+                .collect();
+            truth.push(placement);
+            scanners.push(Scanner { number, beacons });
+        }
+        (scanners, truth)
+    }
+
+    #[test]
+    fn test_generated_chain() {
+        let (scanners, truth) = generate_chain(120, 0);
+        let alignment = align(&scanners, &AlignOptions::default()).unwrap();
+        assert_eq!(alignment.placements, truth);
+        assert_eq!(alignment.beacons.len(), 13 * 121);
+        assert_eq!(alignment.overlaps.len(), 119);
+    }
+
+    #[test]
+    fn test_unplaced() {
+        let (mut scanners, _) = generate_chain(6, 0);
+        // Scanner 3 no longer sees the beacons it shares with scanner 4,
+        // cutting the chain in two.
+        scanners[3].beacons.truncate(13);
+        assert_eq!(
+            align(&scanners, &AlignOptions::default()).map(|a| a.beacons.len()),
+            Err(AlignError {
+                unplaced: vec![4, 5]
+            })
+        );
+        assert_eq!(
+            AlignError {
+                unplaced: vec![3, 4, 5]
+            }
+            .to_string(),
+            "Could not place scanners 3,4,5"
+        );
+        assert!(align(&scanners[..3], &AlignOptions::default()).is_ok());
+        assert_eq!(
+            align(&[], &AlignOptions::default()).map(|a| a.beacons.len()),
+            Ok(0)
+        );
+    }
+
+    #[test]
+    fn test_min_overlap() {
+        let (scanners, _) = generate_chain(4, 0);
+        assert!(align(&scanners, &overlap(13)).is_ok());
+        assert_eq!(
+            align(&scanners, &overlap(14)).map(|a| a.beacons.len()),
+            Err(AlignError {
+                unplaced: vec![1, 2, 3]
+            })
+        );
+    }
+
+    #[test]
+    fn test_noise_tolerance() {
+        let (scanners, truth) = generate_chain(20, 2);
+        assert!(align(&scanners, &AlignOptions::default()).is_err());
+
+        let options = AlignOptions {
+            min_overlap: 12,
+            tolerance: 2,
+        };
+        let alignment = align(&scanners, &options).unwrap();
+        assert_eq!(alignment.beacons.len(), 13 * 21);
+        for (placement, expected) in alignment.placements.iter().zip(&truth) {
+            assert_eq!(placement.rotation, expected.rotation);
+            assert!((placement.translation - expected.translation).abs().max() <= 8);
+        }
     }
 }
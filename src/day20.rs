@@ -1,126 +1,154 @@
-use std::{collections::HashSet, io::BufRead};
+use std::{fmt, io::BufRead, str::FromStr};
 
+use rayon::prelude::*;
+
+// The image as rows of bits, 64 pixels to a word, with every pixel outside
+// it set to `background`.
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct Image {
-    is_light: bool,
-    values: HashSet<(isize, isize)>,
+    width: usize,
+    height: usize,
+    words_per_row: usize,
+    bits: Vec<u64>,
+    background: bool,
 }
 
 impl Image {
-    fn get_surrounding(&self, r: isize, c: isize, bounds: &(isize, isize, isize, isize)) -> usize {
-        let dirs = [
-            (-1, -1),
-            (-1, 0),
-            (-1, 1),
-            (0, -1),
-            (0, 0),
-            (0, 1),
-            (1, -1),
-            (1, 0),
-            (1, 1),
-        ];
-
-        dirs.iter()
-            .map(|(dr, dc)| (r + dr, c + dc))
-            .map(|pos| {
-                (
-                    #[allow(clippy::manual_clamp)]
-                    bounds.0.max(pos.0).min(bounds.1),
-                    #[allow(clippy::manual_clamp)]
-                    bounds.2.max(pos.1).min(bounds.3),
-                )
-            })
-            .map(|pos| match self.values.get(&pos) {
-                Some(_) => self.is_light as usize,
-                None => !self.is_light as usize,
-            })
-            .fold(0, |acc, curr| acc * 2 + curr)
+    fn new(width: usize, height: usize, background: bool) -> Self {
+        let words_per_row = width.div_ceil(64).max(1);
+        Image {
+            width,
+            height,
+            words_per_row,
+            bits: vec![0; words_per_row * height],
+            background,
+        }
     }
-}
 
-fn enhance(image: &mut Image, iep: &[char], flips: bool, should_print: bool) {
-    let bounds = (
-        *image.values.iter().map(|(r, _c)| r).min().unwrap() - 3,
-        *image.values.iter().map(|(r, _c)| r).max().unwrap() + 3,
-        *image.values.iter().map(|(_r, c)| c).min().unwrap() - 3,
-        *image.values.iter().map(|(_r, c)| c).max().unwrap() + 3,
-    );
-    let is_going_to_be_positive = if flips { !image.is_light } else { true };
-    image.values = ((bounds.0 - 3)..=(bounds.1 + 3))
-        .flat_map(|r| {
-            ((bounds.2 - 3)..=(bounds.3 + 3))
-                .filter(|&c| {
-                    if is_going_to_be_positive {
-                        iep[image.get_surrounding(r, c, &bounds)] == '#'
-                    } else {
-                        iep[image.get_surrounding(r, c, &bounds)] == '.'
-                    }
-                })
-                .map(move |c| (r, c))
-                .collect::<Vec<_>>()
-        })
-        .collect();
-    image.is_light = is_going_to_be_positive;
-
-    // println!("Round: {}", i);
-    let positive = if image.is_light { '#' } else { '.' };
-    let negative = if image.is_light { '.' } else { '#' };
-
-    if should_print {
-        for r in (bounds.0 - 3)..=(bounds.1 + 3) {
-            for c in (bounds.2 - 3)..=(bounds.3 + 3) {
-                print!(
-                    "{}",
-                    match image.values.get(&(r, c)) {
-                        Some(_) => positive,
-                        None => negative,
+    fn get(&self, row: isize, col: isize) -> bool {
+        if row < 0 || col < 0 || row as usize >= self.height || col as usize >= self.width {
+            return self.background;
+        }
+        let (row, col) = (row as usize, col as usize);
+        self.bits[row * self.words_per_row + col / 64] >> (col % 64) & 1 == 1
+    }
+
+    fn set(&mut self, row: usize, col: usize) {
+        self.bits[row * self.words_per_row + col / 64] |= 1 << (col % 64);
+    }
+
+    // Every output pixel depends on the 3x3 square around it, so the image
+    // grows by one pixel on each side. Each row slides a 9-bit window along,
+    // shifting in a column of three pixels per step, and rows are worked out
+    // in parallel.
+    fn enhance(&self, algorithm: &[bool; 512]) -> Image {
+        let mut output = Image::new(
+            self.width + 2,
+            self.height + 2,
+            algorithm[if self.background { 511 } else { 0 }],
+        );
+        let width = output.width;
+        output
+            .bits
+            .par_chunks_mut(output.words_per_row)
+            .enumerate()
+            .for_each(|(row, words)| {
+                // Output (row, col) is centred on input (row - 1, col - 1).
+                let top = row as isize - 2;
+                let column = |col: isize| {
+                    (self.get(top, col) as usize) << 6
+                        | (self.get(top + 1, col) as usize) << 3
+                        | self.get(top + 2, col) as usize
+                };
+                let mut window = column(-2) << 1 | column(-1);
+                for col in 0..width {
+                    window = (window << 1) & 0b110_110_110 | column(col as isize);
+                    if algorithm[window] {
+                        words[col / 64] |= 1 << (col % 64);
                     }
-                )
+                }
+            });
+        output
+    }
+
+    // The number of lit pixels, or `None` when infinitely many are lit.
+    fn lit(&self) -> Option<usize> {
+        if self.background {
+            return None;
+        }
+        Some(self.bits.iter().map(|w| w.count_ones() as usize).sum())
+    }
+}
+
+impl FromStr for Image {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rows: Vec<&str> = s
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty())
+            .collect();
+        let width = rows.first().map_or(0, |r| r.len());
+        let mut image = Image::new(width, rows.len(), false);
+        for (row, line) in rows.iter().enumerate() {
+            if line.len() != width {
+                return Err("Rows are not all the same width".to_string());
+            }
+            for (col, c) in line.chars().enumerate() {
+                match c {
+                    '#' => image.set(row, col),
+                    '.' => {}
+                    x => return Err(format!("Invalid pixel {:?}", x)),
+                }
             }
-            println!();
         }
+        Ok(image)
     }
 }
 
-fn star(mut input: impl BufRead, iterations: usize) -> usize {
+impl fmt::Display for Image {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in 0..self.height as isize {
+            for col in 0..self.width as isize {
+                write!(f, "{}", if self.get(row, col) { '#' } else { '.' })?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_algorithm(s: &str) -> Result<[bool; 512], String> {
+    let pixels: Vec<bool> = s
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| match c {
+            '#' => Ok(true),
+            '.' => Ok(false),
+            x => Err(format!("Invalid pixel {:?}", x)),
+        })
+        .collect::<Result<_, _>>()?;
+    pixels
+        .try_into()
+        .map_err(|p: Vec<bool>| format!("Expected 512 pixels, found {}", p.len()))
+}
+
+fn parse_input(mut input: impl BufRead) -> ([bool; 512], Image) {
     let mut buf = String::new();
     input.read_to_string(&mut buf).unwrap();
-    let mut sections = buf.split("\n\n");
+    let (algorithm, image) = buf.split_once("\n\n").unwrap();
+    (parse_algorithm(algorithm).unwrap(), image.parse().unwrap())
+}
 
-    let iep: Vec<_> = sections
-        .next()
-        .unwrap()
-        .chars()
-        .filter(|c| c != &'\n')
-        .collect();
-
-    let mut image = Image {
-        is_light: true,
-        values: sections
-            .next()
-            .unwrap()
-            .lines()
-            .enumerate()
-            .flat_map(|(row, line)| {
-                line.chars()
-                    .enumerate()
-                    .filter(|(_col, c)| match c {
-                        '#' => true,
-                        '.' => false,
-                        x => panic!("{}", x),
-                    })
-                    .map(move |(col, _c)| (row as isize, col as isize))
-            })
-            .collect(),
-    };
-
-    // If the IEP flips the infinite part of the image then we will flip what we are storing.
-    let flips = iep[0] == '#' && iep[511] == '.';
+fn star(input: impl BufRead, iterations: usize) -> usize {
+    let (algorithm, mut image) = parse_input(input);
 
     for _i in 0..iterations {
-        enhance(&mut image, &iep, flips, false);
+        image = image.enhance(&algorithm);
     }
 
-    image.values.len()
+    image.lit().expect("Infinitely many pixels are lit")
 }
 
 pub fn star_one(input: impl BufRead) -> usize {
@@ -134,11 +162,11 @@ pub fn star_two(input: impl BufRead) -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::io::Cursor;
+    use crate::Random;
+    use std::{collections::HashSet, io::Cursor};
 
-    #[test]
-    fn test_star_one() {
-        let input = b"..#.#..#####.#.#.#.###.##.....###.##.#..###.####..#####..#....#..#..##..##
+    const INPUT: &[u8] =
+        b"..#.#..#####.#.#.#.###.##.....###.##.#..###.####..#####..#....#..#..##..##
 #..######.###...####..#..#####..##..#.#####...##.#.#..#.##..#.#......#.###
 .######.###.####...#.##.##..#..#..#####.....#.#....###..#.##......#.....#.
 .#..#..##..#...##.######.####.####.#.#...#.......#..#.#.#...####.##.#.....
@@ -151,24 +179,100 @@ mod tests {
 ##..#
 ..#..
 ..###";
-        assert_eq!(star_one(Cursor::new(input)), 35);
+
+    #[test]
+    fn test_star_one() {
+        assert_eq!(star_one(Cursor::new(INPUT)), 35);
     }
 
     #[test]
     fn test_star_two() {
-        let input = b"..#.#..#####.#.#.#.###.##.....###.##.#..###.####..#####..#....#..#..##..##
-#..######.###...####..#..#####..##..#.#####...##.#.#..#.##..#.#......#.###
-.######.###.####...#.##.##..#..#..#####.....#.#....###..#.##......#.....#.
-.#..#..##..#...##.######.####.####.#.#...#.......#..#.#.#...####.##.#.....
-.#..#...##.#.##..#...##.#.##..###.#......#.#.......#.#.#.####.###.##...#..
-...####.#..#..#.##.#....##..#.####....##...##..#...#......#.#.......#.....
-..##..####..#...#.#.#...##..#.#..###..#####........#..####......#..#
+        assert_eq!(star_two(Cursor::new(INPUT)), 3351);
+    }
 
-#..#.
-#....
-##..#
-..#..
-..###";
-        assert_eq!(star_two(Cursor::new(input)), 3351);
+    // Enhances a set of lit pixels directly, keeping track of the background.
+    fn reference(algorithm: &[bool; 512], image: &Image, rounds: usize) -> Option<usize> {
+        let mut lit: HashSet<(isize, isize)> = (0..image.height as isize)
+            .flat_map(|r| (0..image.width as isize).map(move |c| (r, c)))
+            .filter(|&(r, c)| image.get(r, c))
+            .collect();
+        let mut background = false;
+        for round in 1..=rounds as isize {
+            let get = |r, c| {
+                let inside = r > -round
+                    && c > -round
+                    && r < image.height as isize + round - 1
+                    && c < image.width as isize + round - 1;
+                if inside {
+                    lit.contains(&(r, c))
+                } else {
+                    background
+                }
+            };
+            lit = (-round..image.height as isize + round)
+                .flat_map(|r| (-round..image.width as isize + round).map(move |c| (r, c)))
+                .filter(|&(r, c)| {
+                    let index = (-1..=1)
+                        .flat_map(|dr| (-1..=1).map(move |dc| (dr, dc)))
+                        .fold(0, |acc, (dr, dc)| acc * 2 + get(r + dr, c + dc) as usize);
+                    algorithm[index]
+                })
+                .collect();
+            background = algorithm[if background { 511 } else { 0 }];
+        }
+        if background {
+            None
+        } else {
+            Some(lit.len())
+        }
+    }
+
+    #[test]
+    fn test_matches_reference() {
+        let (_, image) = parse_input(Cursor::new(INPUT));
+        let mut random = Random(3);
+        for case in 0..6 {
+            let mut algorithm = [false; 512];
+            for pixel in algorithm.iter_mut() {
+                *pixel = random.next() % 2 == 1;
+            }
+            // Flipping, dark and lit backgrounds.
+            algorithm[0] = case % 3 != 0;
+            algorithm[511] = case % 3 == 2;
+
+            let mut enhanced = image.clone();
+            for rounds in 1..=6 {
+                enhanced = enhanced.enhance(&algorithm);
+                assert_eq!(enhanced.lit(), reference(&algorithm, &image, rounds));
+            }
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let (algorithm, image) = parse_input(Cursor::new(INPUT));
+        assert_eq!(image.to_string(), "#..#.\n#....\n##..#\n..#..\n..###\n");
+        let enhanced = image.enhance(&algorithm);
+        assert_eq!(
+            enhanced.to_string(),
+            ".##.##.\n#..#.#.\n##.#..#\n####..#\n.#..##.\n..##..#\n...#.#.\n"
+        );
+    }
+
+    #[test]
+    fn test_many_rounds() {
+        let (algorithm, mut image) = parse_input(Cursor::new(INPUT));
+        for _ in 0..300 {
+            image = image.enhance(&algorithm);
+        }
+        assert_eq!((image.width, image.height), (605, 605));
+        assert!(image.lit().unwrap() > 3351);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse_algorithm("#.#").is_err());
+        assert!("#.\n#".parse::<Image>().is_err());
+        assert!("#x".parse::<Image>().is_err());
     }
 }
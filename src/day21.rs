@@ -1,106 +1,213 @@
 use std::io::BufRead;
 
-use cached::proc_macro::cached;
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Die {
+    // Rolls 1, 2, ... up to `sides` and then starts again from 1.
+    Deterministic { sides: usize, next: usize },
+    // Rolls from a linear congruential generator.
+    Seeded { sides: usize, state: u64 },
+}
 
-pub fn star_one(mut input: impl BufRead) -> usize {
-    let mut data = String::new();
-    let _res = input.read_to_string(&mut data);
+impl Die {
+    fn deterministic(sides: usize) -> Self {
+        Die::Deterministic { sides, next: 1 }
+    }
+
+    #[allow(dead_code)]
+    fn seeded(sides: usize, seed: u64) -> Self {
+        Die::Seeded { sides, state: seed }
+    }
+
+    fn roll(&mut self) -> usize {
+        match self {
+            Die::Deterministic { sides, next } => {
+                let value = *next;
+                *next = value % *sides + 1;
+                value
+            }
+            Die::Seeded { sides, state } => crate::lcg(state) as usize % *sides + 1,
+        }
+    }
+}
+
+// The practice game: players take turns rolling the die `rolls` times,
+// moving around a board of spaces numbered 1 to `board`, until one of them
+// scores at least `target`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PracticeGame {
+    board: usize,
+    target: usize,
+    rolls: usize,
+}
 
-    let (mut pos1, mut pos2) = {
-        let (p1, p2) = data.split_once('\n').unwrap();
-        (
-            p1.split_once(": ").unwrap().1.parse::<usize>().unwrap(),
-            p2.split_once(": ").unwrap().1.parse::<usize>().unwrap(),
-        )
-    };
-    let mut dice = 1;
-    let mut turn = true;
-
-    let mut score1 = 0;
-    let mut score2 = 0;
-
-    pos1 -= 1;
-    pos2 -= 1;
-
-    while score1 < 1000 && score2 < 1000 {
-        let value = dice + dice + 1 + dice + 2;
-        if turn {
-            pos1 += value;
-            pos1 %= 10;
-            score1 += pos1 + 1;
-        } else {
-            pos2 += value;
-            pos2 %= 10;
-            score2 += pos2 + 1;
+impl Default for PracticeGame {
+    fn default() -> Self {
+        PracticeGame {
+            board: 10,
+            target: 1000,
+            rolls: 3,
         }
-        turn = !turn;
-        dice += 3;
     }
-    score1.min(score2) * (dice - 1)
 }
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
-struct Player {
+// One player's turn, with the space they ended on and their score after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Turn {
+    player: usize,
+    rolls: Vec<usize>,
     position: usize,
     score: usize,
 }
 
-#[cached]
-fn play(player1: Player, player2: Player, turn: bool) -> (usize, usize) {
-    let max_score = 20;
-    if player1.score > max_score {
-        (1, 0)
-    } else if player2.score > max_score {
-        (0, 1)
-    } else {
-        [(3, 1), (4, 3), (5, 6), (6, 7), (7, 6), (8, 3), (9, 1)]
-            .into_iter()
-            .map(|(roll_sum, times)| {
-                // println!("{} {} {:?}", player1.score, player2.score, dice);
-                let mut player1 = player1.clone();
-                let mut player2 = player2.clone();
-
-                if turn {
-                    player1.position = (player1.position + roll_sum) % 10;
-                    player1.score += player1.position + 1;
-                } else {
-                    player2.position = (player2.position + roll_sum) % 10;
-                    player2.score += player2.position + 1;
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Outcome {
+    winner: usize,
+    scores: Vec<usize>,
+    history: Vec<Turn>,
+}
+
+impl Outcome {
+    fn rolls(&self) -> usize {
+        self.history.iter().map(|turn| turn.rolls.len()).sum()
+    }
+}
+
+impl PracticeGame {
+    fn play(&self, start: &[usize], die: &mut Die) -> Outcome {
+        assert!(!start.is_empty(), "The game needs at least one player");
+        let mut positions = start.to_vec();
+        let mut scores = vec![0; start.len()];
+        let mut history = Vec::new();
+        for player in (0..start.len()).cycle() {
+            let rolls: Vec<usize> = (0..self.rolls).map(|_| die.roll()).collect();
+            let moved = positions[player] - 1 + rolls.iter().sum::<usize>();
+            positions[player] = moved % self.board + 1;
+            scores[player] += positions[player];
+            history.push(Turn {
+                player,
+                rolls,
+                position: positions[player],
+                score: scores[player],
+            });
+            if scores[player] >= self.target {
+                return Outcome {
+                    winner: player,
+                    scores,
+                    history,
+                };
+            }
+        }
+        unreachable!()
+    }
+}
+
+pub fn star_one(input: impl BufRead) -> usize {
+    let start = parse_input(input);
+
+    let outcome = PracticeGame::default().play(&start, &mut Die::deterministic(100));
+    outcome.scores.iter().min().unwrap() * outcome.rolls()
+}
+
+// A game of Dirac Dice between two players. Each turn the player rolls a
+// `sides` sided die `rolls` times, moves forward around a board of spaces
+// numbered 1 to `board` and scores the space they land on, and the first to
+// reach `target` wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DiracDice {
+    board: usize,
+    target: usize,
+    sides: usize,
+    rolls: usize,
+}
+
+impl Default for DiracDice {
+    fn default() -> Self {
+        DiracDice {
+            board: 10,
+            target: 21,
+            sides: 3,
+            rolls: 3,
+        }
+    }
+}
+
+impl DiracDice {
+    // How many ways each total of a turn's rolls comes up, indexed by total.
+    fn roll_distribution(&self) -> Vec<u128> {
+        let mut ways = vec![1];
+        for _ in 0..self.rolls {
+            let mut next = vec![0; ways.len() + self.sides];
+            for (total, &count) in ways.iter().enumerate() {
+                for face in 1..=self.sides {
+                    next[total + face] += count;
                 }
-                let res = play(player1, player2, !turn);
-                (res.0 * times, res.1 * times)
-            })
-            .fold((0, 0), |acc, (p1, p2)| (acc.0 + p1, acc.1 + p2))
+            }
+            ways = next;
+        }
+        ways
+    }
+
+    // The number of universes each player wins in, starting on the given
+    // spaces with the first player to move.
+    //
+    // `table` holds, for the player about to move and the other player, the
+    // universes each of them goes on to win from every pair of positions
+    // and scores below the target. A turn always raises the mover's score, so
+    // filling it in order of falling total score only ever looks up states
+    // that are already done.
+    fn wins(&self, start: [usize; 2]) -> [u128; 2] {
+        assert!(self.target > 0, "The target score must be positive");
+        let (board, target) = (self.board, self.target);
+        let distribution = self.roll_distribution();
+        let index = |mover: usize, mover_score: usize, other: usize, other_score: usize| {
+            ((mover * target + mover_score) * board + other) * target + other_score
+        };
+        let mut table = vec![[0u128; 2]; board * board * target * target];
+
+        for total in (0..=2 * (target - 1)).rev() {
+            let scores = total.saturating_sub(target - 1)..=total.min(target - 1);
+            for mover_score in scores {
+                let other_score = total - mover_score;
+                for mover in 0..board {
+                    for other in 0..board {
+                        let mut wins = [0; 2];
+                        for (roll, &ways) in distribution.iter().enumerate() {
+                            let position = (mover + roll) % board;
+                            let score = mover_score + position + 1;
+                            if score >= target {
+                                wins[0] += ways;
+                            } else {
+                                let [next_mover, next_other] =
+                                    table[index(other, other_score, position, score)];
+                                wins[0] += ways * next_other;
+                                wins[1] += ways * next_mover;
+                            }
+                        }
+                        table[index(mover, mover_score, other, other_score)] = wins;
+                    }
+                }
+            }
+        }
+
+        table[index((start[0] - 1) % board, 0, (start[1] - 1) % board, 0)]
     }
 }
 
-pub fn star_two(mut input: impl BufRead) -> usize {
+// Starting positions of the players, in order.
+fn parse_input(mut input: impl BufRead) -> Vec<usize> {
     let mut data = String::new();
     let _res = input.read_to_string(&mut data);
+    data.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.split_once(": ").unwrap().1.trim().parse().unwrap())
+        .collect()
+}
 
-    let (pos1, pos2) = {
-        let (p1, p2) = data.split_once('\n').unwrap();
-        (
-            p1.split_once(": ").unwrap().1.parse::<usize>().unwrap(),
-            p2.split_once(": ").unwrap().1.parse::<usize>().unwrap(),
-        )
-    };
-
-    let player1 = Player {
-        position: pos1 - 1,
-        score: 0,
-    };
-
-    let player2 = Player {
-        position: pos2 - 1,
-        score: 0,
-    };
-
-    let turn = true;
-
-    let res = play(player1, player2, turn);
-    println!("{:?}", res);
-    res.0.max(res.1)
+pub fn star_two(input: impl BufRead) -> usize {
+    let start = parse_input(input);
+
+    let wins = DiracDice::default().wins([start[0], start[1]]);
+    usize::try_from(wins[0].max(wins[1])).unwrap()
 }
 
 #[cfg(test)]
@@ -121,4 +228,138 @@ Player 2 starting position: 8";
 Player 2 starting position: 8";
         assert_eq!(star_two(Cursor::new(input)), 444356092776315);
     }
+
+    #[test]
+    fn test_roll_distribution() {
+        assert_eq!(
+            DiracDice::default().roll_distribution(),
+            [0, 0, 0, 1, 3, 6, 7, 6, 3, 1]
+        );
+        let d6 = DiracDice {
+            sides: 6,
+            rolls: 1,
+            ..DiracDice::default()
+        };
+        assert_eq!(d6.roll_distribution(), [0, 1, 1, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_wins() {
+        assert_eq!(
+            DiracDice::default().wins([4, 8]),
+            [444356092776315, 341960390180808]
+        );
+        // The first player always wins straight away.
+        let game = DiracDice {
+            target: 1,
+            ..DiracDice::default()
+        };
+        assert_eq!(game.wins([4, 8]), [27, 0]);
+    }
+
+    fn reference(game: &DiracDice, players: [(usize, usize); 2]) -> [u128; 2] {
+        let mut wins = [0; 2];
+        for (roll, &ways) in game.roll_distribution().iter().enumerate() {
+            let position = (players[0].0 + roll - 1) % game.board + 1;
+            let score = players[0].1 + position;
+            if score >= game.target {
+                wins[0] += ways;
+            } else {
+                let [other, mover] = reference(game, [players[1], (position, score)]);
+                wins[0] += ways * mover;
+                wins[1] += ways * other;
+            }
+        }
+        wins
+    }
+
+    #[test]
+    fn test_matches_reference() {
+        for (board, target, sides, rolls) in [(7, 8, 2, 2), (5, 10, 4, 1), (12, 9, 3, 2)] {
+            let game = DiracDice {
+                board,
+                target,
+                sides,
+                rolls,
+            };
+            for start in [[1, 1], [2, 5], [board, 3]] {
+                assert_eq!(
+                    game.wins(start),
+                    reference(&game, [(start[0], 0), (start[1], 0)])
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_practice_history() {
+        let outcome = PracticeGame::default().play(&[4, 8], &mut Die::deterministic(100));
+        assert_eq!(
+            outcome.history[..3],
+            [
+                Turn {
+                    player: 0,
+                    rolls: vec![1, 2, 3],
+                    position: 10,
+                    score: 10
+                },
+                Turn {
+                    player: 1,
+                    rolls: vec![4, 5, 6],
+                    position: 3,
+                    score: 3
+                },
+                Turn {
+                    player: 0,
+                    rolls: vec![7, 8, 9],
+                    position: 4,
+                    score: 14
+                },
+            ]
+        );
+        assert_eq!(outcome.winner, 0);
+        assert_eq!(outcome.scores, [1000, 745]);
+        assert_eq!(outcome.rolls(), 993);
+        // The die wraps around after 100.
+        assert_eq!(outcome.history[33].rolls, [100, 1, 2]);
+    }
+
+    #[test]
+    fn test_dice() {
+        let mut die = Die::deterministic(4);
+        assert_eq!(
+            (0..6).map(|_| die.roll()).collect::<Vec<_>>(),
+            [1, 2, 3, 4, 1, 2]
+        );
+
+        let mut a = Die::seeded(6, 42);
+        let mut b = Die::seeded(6, 42);
+        let rolls: Vec<usize> = (0..1000).map(|_| a.roll()).collect();
+        assert!(rolls.iter().all(|r| (1..=6).contains(r)));
+        assert!((1..=6).all(|face| rolls.contains(&face)));
+        assert_eq!(rolls, (0..1000).map(|_| b.roll()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_many_players() {
+        let game = PracticeGame {
+            board: 10,
+            target: 100,
+            rolls: 3,
+        };
+        for mut die in [Die::deterministic(100), Die::seeded(20, 7)] {
+            let outcome = game.play(&[1, 2, 3, 4, 5], &mut die);
+            let last = outcome.history.last().unwrap();
+            assert_eq!(last.player, outcome.winner);
+            assert!(outcome.scores[outcome.winner] >= 100);
+            for (i, turn) in outcome.history.iter().enumerate() {
+                assert_eq!(turn.player, i % 5);
+                assert!((1..=10).contains(&turn.position));
+            }
+            for (player, &score) in outcome.scores.iter().enumerate() {
+                let turns = outcome.history.iter().filter(|t| t.player == player);
+                assert_eq!(turns.map(|t| t.position).sum::<usize>(), score);
+            }
+        }
+    }
 }
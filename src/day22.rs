@@ -1,24 +1,51 @@
-use std::{
-    cmp::{max, min},
-    collections::HashSet,
-    io::BufRead,
-};
+use std::{io::BufRead, str::FromStr};
 
-#[derive(Eq, Hash, PartialEq)]
-struct Cuboid(isize, isize, isize, isize, isize, isize);
+use rayon::prelude::*;
+
+// An axis-aligned box of cubes, with inclusive bounds along x, y and z.
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
+struct Cuboid {
+    min: [isize; 3],
+    max: [isize; 3],
+}
+
+impl FromStr for Cuboid {
+    type Err = String;
+
+    // Parses `x=10..12,y=10..12,z=10..12`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut cuboid = Cuboid {
+            min: [0; 3],
+            max: [0; 3],
+        };
+        let ranges: Vec<&str> = s.trim().split(',').collect();
+        if ranges.len() != 3 {
+            return Err(format!("Expected three ranges in {:?}", s));
+        }
+        for (axis, range) in ranges.into_iter().enumerate() {
+            let (lower, upper) = range
+                .split_once('=')
+                .and_then(|(_axis, values)| values.split_once(".."))
+                .ok_or_else(|| format!("Invalid range {:?}", range))?;
+            cuboid.min[axis] = lower
+                .parse()
+                .map_err(|_| format!("Invalid range {:?}", range))?;
+            cuboid.max[axis] = upper
+                .parse()
+                .map_err(|_| format!("Invalid range {:?}", range))?;
+        }
+        Ok(cuboid)
+    }
+}
 
 impl Cuboid {
     fn intersect(&self, other: &Cuboid) -> Option<Cuboid> {
-        let c = Cuboid(
-            max(self.0, other.0),
-            min(self.1, other.1),
-            max(self.2, other.2),
-            min(self.3, other.3),
-            max(self.4, other.4),
-            min(self.5, other.5),
-        );
+        let c = Cuboid {
+            min: [0, 1, 2].map(|axis| self.min[axis].max(other.min[axis])),
+            max: [0, 1, 2].map(|axis| self.max[axis].min(other.max[axis])),
+        };
 
-        if c.0 > c.1 || c.2 > c.3 || c.4 > c.5 {
+        if (0..3).any(|axis| c.min[axis] > c.max[axis]) {
             None
         } else {
             Some(c)
@@ -26,175 +53,252 @@ impl Cuboid {
     }
 
     fn volume(&self) -> isize {
-        (self.1 - self.0 + 1) * (self.3 - self.2 + 1) * (self.5 - self.4 + 1)
+        (0..3)
+            .map(|axis| self.max[axis] - self.min[axis] + 1)
+            .product()
     }
-}
 
-pub fn star_one(input: impl BufRead) -> usize {
-    let data = input
-        .lines()
-        .map(|line| {
-            let line = line.unwrap();
-            let (new_value, cuboid) = line.split_once(' ').unwrap();
-            let mut cuboid = cuboid.split(',').map(|s| {
-                let (_prefix, values) = s.split_once('=').unwrap();
-                let (lower, upper) = values.split_once("..").unwrap();
-                (
-                    lower.parse::<isize>().unwrap(),
-                    upper.parse::<isize>().unwrap(),
-                )
-            });
-            (
-                new_value == "on",
-                (
-                    cuboid.next().unwrap(),
-                    cuboid.next().unwrap(),
-                    cuboid.next().unwrap(),
-                ),
-            )
-        })
-        .filter(|(_ins, cuboid)| {
-            cuboid.0 .0 >= -50
-                && cuboid.0 .1 <= 50
-                && cuboid.1 .0 >= -50
-                && cuboid.1 .1 <= 50
-                && cuboid.2 .0 >= -50
-                && cuboid.2 .1 <= 50
-        });
-
-    let mut on = HashSet::new();
-
-    for (instruction, cuboid) in data {
-        for x in (cuboid.0).0..=cuboid.0 .1 {
-            for y in cuboid.1 .0..=cuboid.1 .1 {
-                if (-50..=50).contains(&y) {
-                    for z in cuboid.2 .0..=cuboid.2 .1 {
-                        if (-50..=50).contains(&y) {
-                            if instruction {
-                                on.insert((x, y, z));
-                            } else {
-                                on.remove(&(x, y, z));
-                            }
-                        }
-                    }
-                }
+    #[allow(dead_code)]
+    fn contains(&self, point: [isize; 3]) -> bool {
+        (0..3).all(|axis| (self.min[axis]..=self.max[axis]).contains(&point[axis]))
+    }
+
+    // The part of this cuboid outside `other`, as up to six disjoint boxes:
+    // the slabs either side of the overlap along x, then along y within the
+    // overlap's x range, then along z.
+    fn subtract(&self, other: &Cuboid) -> Vec<Cuboid> {
+        let Some(overlap) = self.intersect(other) else {
+            return vec![*self];
+        };
+        let mut pieces = Vec::new();
+        let mut rest = *self;
+        for axis in 0..3 {
+            if rest.min[axis] < overlap.min[axis] {
+                let mut below = rest;
+                below.max[axis] = overlap.min[axis] - 1;
+                pieces.push(below);
+            }
+            if rest.max[axis] > overlap.max[axis] {
+                let mut above = rest;
+                above.min[axis] = overlap.max[axis] + 1;
+                pieces.push(above);
             }
+            rest.min[axis] = overlap.min[axis];
+            rest.max[axis] = overlap.max[axis];
         }
+        pieces
     }
+}
 
-    on.len()
+// A set of cubes stored as disjoint cuboids.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct CuboidSet {
+    boxes: Vec<Cuboid>,
 }
 
-pub fn star_two(input: impl BufRead) -> usize {
-    let data = input
+impl From<Cuboid> for CuboidSet {
+    fn from(cuboid: Cuboid) -> Self {
+        CuboidSet {
+            boxes: vec![cuboid],
+        }
+    }
+}
+
+impl CuboidSet {
+    fn volume(&self) -> isize {
+        self.boxes.iter().map(|b| b.volume()).sum()
+    }
+
+    #[allow(dead_code)]
+    fn contains(&self, point: [isize; 3]) -> bool {
+        self.boxes.iter().any(|b| b.contains(point))
+    }
+
+    fn union(&self, other: &CuboidSet) -> CuboidSet {
+        let mut union = self.clone();
+        union.boxes.extend(other.difference(self).boxes);
+        union
+    }
+
+    #[allow(dead_code)]
+    fn intersection(&self, other: &CuboidSet) -> CuboidSet {
+        CuboidSet {
+            boxes: self
+                .boxes
+                .iter()
+                .flat_map(|a| other.boxes.iter().filter_map(|b| a.intersect(b)))
+                .collect(),
+        }
+    }
+
+    fn difference(&self, other: &CuboidSet) -> CuboidSet {
+        let mut boxes = self.boxes.clone();
+        for b in &other.boxes {
+            boxes = boxes.iter().flat_map(|a| a.subtract(b)).collect();
+        }
+        CuboidSet { boxes }
+    }
+}
+
+// The reboot steps, each turning a cuboid on or off.
+fn parse_input(input: impl BufRead) -> Vec<(bool, Cuboid)> {
+    input
         .lines()
         .map(|line| {
             let line = line.unwrap();
             let (new_value, cuboid) = line.split_once(' ').unwrap();
-            let mut cuboid = cuboid.split(',').map(|s| {
-                let (_prefix, values) = s.split_once('=').unwrap();
-                let (lower, upper) = values.split_once("..").unwrap();
-                (
-                    lower.parse::<isize>().unwrap(),
-                    upper.parse::<isize>().unwrap(),
-                )
-            });
-            (
-                new_value == "on",
-                (
-                    cuboid.next().unwrap(),
-                    cuboid.next().unwrap(),
-                    cuboid.next().unwrap(),
-                ),
-            )
+            (new_value == "on", cuboid.parse().unwrap())
         })
-        .map(|(ins, cuboid)| {
-            (
-                ins,
-                Cuboid(
-                    cuboid.0 .0,
-                    cuboid.0 .1,
-                    cuboid.1 .0,
-                    cuboid.1 .1,
-                    cuboid.2 .0,
-                    cuboid.2 .1,
-                ),
-            )
+        .collect()
+}
+
+fn reboot(steps: impl IntoIterator<Item = (bool, Cuboid)>) -> CuboidSet {
+    steps
+        .into_iter()
+        .fold(CuboidSet::default(), |cubes, (on, cuboid)| {
+            let cuboid = CuboidSet::from(cuboid);
+            if on {
+                cubes.union(&cuboid)
+            } else {
+                cubes.difference(&cuboid)
+            }
         })
-        .fold(
-            Vec::new(),
-            |mut cores: Vec<(bool, Cuboid)>, (ins, cuboid)| {
-                let additional_cores: Vec<_> = cores
-                    .iter()
-                    .filter_map(|(core_ins, core)| {
-                        cuboid
-                            .intersect(core)
-                            .map(|intersection| (!core_ins, intersection))
+}
+
+// Sorted, deduplicated boundaries of the cuboids along `axis`: each
+// cuboid's minimum and one past its maximum.
+fn boundaries<'a>(cuboids: impl Iterator<Item = &'a Cuboid>, axis: usize) -> Vec<isize> {
+    let mut bounds: Vec<isize> = cuboids
+        .flat_map(|c| [c.min[axis], c.max[axis] + 1])
+        .collect();
+    bounds.sort_unstable();
+    bounds.dedup();
+    bounds
+}
+
+// The area of the y-z cross-section left on by the steps, in order, with
+// y and z compressed to the boundaries of these steps alone.
+fn cross_section(steps: &[&(bool, Cuboid)]) -> isize {
+    if !steps.iter().any(|(on, _)| *on) {
+        return 0;
+    }
+    let ys = boundaries(steps.iter().map(|(_, c)| c), 1);
+    let zs = boundaries(steps.iter().map(|(_, c)| c), 2);
+    let index = |bounds: &[isize], value: isize| bounds.binary_search(&value).unwrap();
+    let width = zs.len() - 1;
+    let mut grid = vec![false; (ys.len() - 1) * width];
+    for (on, c) in steps {
+        for y in index(&ys, c.min[1])..index(&ys, c.max[1] + 1) {
+            grid[y * width + index(&zs, c.min[2])..y * width + index(&zs, c.max[2] + 1)].fill(*on);
+        }
+    }
+    grid.chunks(width)
+        .zip(ys.windows(2))
+        .map(|(row, y)| {
+            let length: isize = row
+                .iter()
+                .zip(zs.windows(2))
+                .filter(|(on, _)| **on)
+                .map(|(_, z)| z[1] - z[0])
+                .sum();
+            length * (y[1] - y[0])
+        })
+        .sum()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Solver {
+    // Adds every step's intersection with each earlier cuboid counted so far,
+    // with the opposite sign. The list can double with every step when the
+    // steps all overlap.
+    Signed,
+    // Sweeps over x, slab by slab between consecutive cuboid boundaries, and
+    // paints the steps covering each slab onto a compressed y-z grid. Slabs
+    // are worked out in parallel.
+    Compressed,
+}
+
+impl Solver {
+    // The number of cubes left on after the steps.
+    fn volume(&self, steps: &[(bool, Cuboid)]) -> isize {
+        match self {
+            Solver::Signed => signed_volume(steps),
+            Solver::Compressed => {
+                let xs = boundaries(steps.iter().map(|(_, c)| c), 0);
+                xs.par_windows(2)
+                    .map(|x| {
+                        let covering: Vec<&(bool, Cuboid)> = steps
+                            .iter()
+                            .filter(|(_, c)| c.min[0] <= x[0] && x[0] <= c.max[0])
+                            .collect();
+                        (x[1] - x[0]) * cross_section(&covering)
                     })
-                    .collect();
+                    .sum()
+            }
+        }
+    }
+}
 
-                cores.extend(additional_cores);
-                if ins {
-                    cores.push((ins, cuboid));
-                }
-                cores
-            },
-        );
+fn signed_volume(steps: &[(bool, Cuboid)]) -> isize {
+    let data = steps.iter().fold(
+        Vec::new(),
+        |mut cores: Vec<(bool, Cuboid)>, &(ins, cuboid)| {
+            let additional_cores: Vec<_> = cores
+                .iter()
+                .filter_map(|(core_ins, core)| {
+                    cuboid
+                        .intersect(core)
+                        .map(|intersection| (!core_ins, intersection))
+                })
+                .collect();
+
+            cores.extend(additional_cores);
+            if ins {
+                cores.push((ins, cuboid));
+            }
+            cores
+        },
+    );
 
     data.iter()
         .map(|(ins, cuboid)| {
             let modifer = if *ins { 1 } else { -1 };
-            let res = modifer * cuboid.volume();
-            if !ins {
-                println!("{}", res)
-            }
-            res
+            modifer * cuboid.volume()
         })
-        .sum::<isize>() as usize
+        .sum::<isize>()
+}
+
+pub fn star_one(input: impl BufRead) -> usize {
+    let region = Cuboid {
+        min: [-50; 3],
+        max: [50; 3],
+    };
+    let steps = parse_input(input)
+        .into_iter()
+        .filter_map(|(on, cuboid)| Some((on, cuboid.intersect(&region)?)));
+
+    reboot(steps).volume() as usize
+}
+
+pub fn star_two(input: impl BufRead) -> usize {
+    Solver::Compressed.volume(&parse_input(input)) as usize
+}
+
+pub fn star_two_signed(input: impl BufRead) -> usize {
+    Solver::Signed.volume(&parse_input(input)) as usize
+}
+
+pub fn star_two_cuboids(input: impl BufRead) -> usize {
+    reboot(parse_input(input)).volume() as usize
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Random;
     use std::io::Cursor;
 
-    #[test]
-    fn test_star_one() {
-        let input = b"on x=10..12,y=10..12,z=10..12
-on x=11..13,y=11..13,z=11..13
-off x=9..11,y=9..11,z=9..11
-on x=10..10,y=10..10,z=10..10";
-        assert_eq!(star_one(Cursor::new(input)), 39);
-
-        let input = b"on x=-20..26,y=-36..17,z=-47..7
-on x=-20..33,y=-21..23,z=-26..28
-on x=-22..28,y=-29..23,z=-38..16
-on x=-46..7,y=-6..46,z=-50..-1
-on x=-49..1,y=-3..46,z=-24..28
-on x=2..47,y=-22..22,z=-23..27
-on x=-27..23,y=-28..26,z=-21..29
-on x=-39..5,y=-6..47,z=-3..44
-on x=-30..21,y=-8..43,z=-13..34
-on x=-22..26,y=-27..20,z=-29..19
-off x=-48..-32,y=26..41,z=-47..-37
-on x=-12..35,y=6..50,z=-50..-2
-off x=-48..-32,y=-32..-16,z=-15..-5
-on x=-18..26,y=-33..15,z=-7..46
-off x=-40..-22,y=-38..-28,z=23..41
-on x=-16..35,y=-41..10,z=-47..6
-off x=-32..-23,y=11..30,z=-14..3
-on x=-49..-5,y=-3..45,z=-29..18
-off x=18..30,y=-20..-8,z=-3..13
-on x=-41..9,y=-7..43,z=-33..15
-on x=-54112..-39298,y=-85059..-49293,z=-27449..7877
-on x=967..23432,y=45373..81175,z=27513..53682";
-
-        assert_eq!(star_one(Cursor::new(input)), 590784);
-    }
-
-    #[test]
-    fn test_star_two() {
-        let input = b"on x=-5..47,y=-31..22,z=-19..33
+    const LARGE: &[u8] = b"on x=-5..47,y=-31..22,z=-19..33
 on x=-44..5,y=-27..21,z=-14..35
 on x=-49..-1,y=-11..42,z=-10..38
 on x=-20..34,y=-40..6,z=-44..1
@@ -254,6 +358,196 @@ off x=-27365..46395,y=31009..98017,z=15428..76570
 off x=-70369..-16548,y=22648..78696,z=-1892..86821
 on x=-53470..21291,y=-120233..-33476,z=-44150..38147
 off x=-93533..-4276,y=-16170..68771,z=-104985..-24507";
-        assert_eq!(star_two(Cursor::new(input)), 2758514936282235);
+
+    #[test]
+    fn test_star_one() {
+        let input = b"on x=10..12,y=10..12,z=10..12
+on x=11..13,y=11..13,z=11..13
+off x=9..11,y=9..11,z=9..11
+on x=10..10,y=10..10,z=10..10";
+        assert_eq!(star_one(Cursor::new(input)), 39);
+
+        let input = b"on x=-20..26,y=-36..17,z=-47..7
+on x=-20..33,y=-21..23,z=-26..28
+on x=-22..28,y=-29..23,z=-38..16
+on x=-46..7,y=-6..46,z=-50..-1
+on x=-49..1,y=-3..46,z=-24..28
+on x=2..47,y=-22..22,z=-23..27
+on x=-27..23,y=-28..26,z=-21..29
+on x=-39..5,y=-6..47,z=-3..44
+on x=-30..21,y=-8..43,z=-13..34
+on x=-22..26,y=-27..20,z=-29..19
+off x=-48..-32,y=26..41,z=-47..-37
+on x=-12..35,y=6..50,z=-50..-2
+off x=-48..-32,y=-32..-16,z=-15..-5
+on x=-18..26,y=-33..15,z=-7..46
+off x=-40..-22,y=-38..-28,z=23..41
+on x=-16..35,y=-41..10,z=-47..6
+off x=-32..-23,y=11..30,z=-14..3
+on x=-49..-5,y=-3..45,z=-29..18
+off x=18..30,y=-20..-8,z=-3..13
+on x=-41..9,y=-7..43,z=-33..15
+on x=-54112..-39298,y=-85059..-49293,z=-27449..7877
+on x=967..23432,y=45373..81175,z=27513..53682";
+
+        assert_eq!(star_one(Cursor::new(input)), 590784);
+    }
+
+    #[test]
+    fn test_star_two() {
+        assert_eq!(star_two(Cursor::new(LARGE)), 2758514936282235);
+    }
+
+    #[test]
+    fn test_variants() {
+        for (name, star_one, star_two) in crate::get_variants(22) {
+            assert_eq!(star_one(Box::new(Cursor::new(LARGE))), 474140, "{}", name);
+            assert_eq!(
+                star_two(Box::new(Cursor::new(LARGE))),
+                2758514936282235,
+                "{}",
+                name
+            );
+        }
+    }
+
+    fn cuboid(s: &str) -> Cuboid {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_cuboid() {
+        assert_eq!(
+            cuboid("x=-20..26,y=-36..17,z=-47..7"),
+            Cuboid {
+                min: [-20, -36, -47],
+                max: [26, 17, 7]
+            }
+        );
+        assert!("x=1..2,y=3..4".parse::<Cuboid>().is_err());
+        assert!("x=1..2,y=3..a,z=1..1".parse::<Cuboid>().is_err());
+    }
+
+    #[test]
+    fn test_subtract() {
+        let a = cuboid("x=0..9,y=0..9,z=0..9");
+        let b = cuboid("x=3..5,y=-5..4,z=8..20");
+        let pieces = a.subtract(&b);
+        assert_eq!(
+            pieces.iter().map(|p| p.volume()).sum::<isize>(),
+            1000 - 3 * 5 * 2
+        );
+        for (p, q) in pieces
+            .iter()
+            .enumerate()
+            .flat_map(|(i, p)| pieces[i + 1..].iter().map(move |q| (p, q)))
+        {
+            assert_eq!(p.intersect(q), None);
+        }
+        assert!(pieces.iter().all(|p| p.intersect(&b).is_none()));
+        assert_eq!(a.subtract(&cuboid("x=20..30,y=0..0,z=0..0")), vec![a]);
+        assert_eq!(a.subtract(&a), vec![]);
+    }
+
+    #[test]
+    fn test_set_algebra() {
+        let a = CuboidSet::from(cuboid("x=0..3,y=0..3,z=0..3"));
+        let b = CuboidSet::from(cuboid("x=2..5,y=2..5,z=0..0"));
+        assert_eq!(a.union(&b).volume(), 64 + 16 - 4);
+        assert_eq!(a.intersection(&b).volume(), 4);
+        assert_eq!(a.difference(&b).volume(), 60);
+        assert_eq!(b.difference(&a).volume(), 12);
+
+        let union = a.union(&b);
+        for point in [[0, 0, 0], [3, 3, 3], [5, 5, 0], [4, 2, 0]] {
+            assert!(union.contains(point));
+        }
+        for point in [[5, 5, 1], [4, 1, 0], [-1, 0, 0]] {
+            assert!(!union.contains(point));
+        }
+        assert!(!a.difference(&b).contains([2, 2, 0]));
+        assert!(a.intersection(&b).contains([3, 3, 0]));
+    }
+
+    #[test]
+    fn test_reboot() {
+        let steps = parse_input(Cursor::new(LARGE));
+        let cubes = reboot(steps.clone());
+        assert_eq!(cubes.volume(), 2758514936282235);
+        for (i, a) in cubes.boxes.iter().enumerate() {
+            for b in &cubes.boxes[i + 1..] {
+                assert_eq!(a.intersect(b), None);
+            }
+        }
+
+        // The last step to cover a cube decides whether it is on.
+        for point in [
+            [0, 0, 0],
+            [-50000, 40000, 30000],
+            [10, -10, 20],
+            [40000, -20000, 0],
+        ] {
+            let on = steps
+                .iter()
+                .rev()
+                .find(|(_, c)| c.contains(point))
+                .is_some_and(|&(on, _)| on);
+            assert_eq!(cubes.contains(point), on);
+        }
+    }
+
+    // Random steps within -range..=range on every axis, mostly on.
+    fn generate_steps(seed: u64, count: usize, range: isize) -> Vec<(bool, Cuboid)> {
+        let mut random = Random(seed);
+        (0..count)
+            .map(|_| {
+                let on = random.range(2) >= 0;
+                let mut min = [0; 3];
+                let mut max = [0; 3];
+                for axis in 0..3 {
+                    let (a, b) = (random.range(range), random.range(range));
+                    min[axis] = a.min(b);
+                    max[axis] = a.max(b);
+                }
+                (on, Cuboid { min, max })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_solvers_agree() {
+        let steps = parse_input(Cursor::new(LARGE));
+        assert_eq!(Solver::Signed.volume(&steps), 2758514936282235);
+        assert_eq!(Solver::Compressed.volume(&steps), 2758514936282235);
+        assert_eq!(star_two_cuboids(Cursor::new(LARGE)), 2758514936282235);
+
+        for seed in 0..20 {
+            let steps = generate_steps(seed, 30, 1000);
+            let signed = Solver::Signed.volume(&steps);
+            assert_eq!(Solver::Compressed.volume(&steps), signed, "seed {}", seed);
+            assert_eq!(reboot(steps).volume(), signed, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn test_compressed_matches_counting() {
+        for seed in 0..20 {
+            let steps = generate_steps(seed, 15, 6);
+            let mut count = 0;
+            for x in -6..=6 {
+                for y in -6..=6 {
+                    for z in -6..=6 {
+                        let on = steps
+                            .iter()
+                            .rev()
+                            .find(|(_, c)| c.contains([x, y, z]))
+                            .is_some_and(|&(on, _)| on);
+                        count += on as isize;
+                    }
+                }
+            }
+            assert_eq!(Solver::Compressed.volume(&steps), count, "seed {}", seed);
+        }
+        assert_eq!(Solver::Compressed.volume(&[]), 0);
     }
 }
@@ -29,6 +29,7 @@ mod day22;
 mod day23;
 mod day24;
 mod day25;
+mod matrix;
 
 type DayFn = fn(Box<dyn BufRead>) -> usize;
 
@@ -265,6 +266,28 @@ pub fn get_day(day: usize) -> (DayFn, DayFn, PathBuf) {
     }
 }
 
+// Alternative implementations of a day, by name, for benchmarking against the
+// ones returned by `get_day`.
+pub fn get_variants(day: usize) -> Vec<(&'static str, DayFn, DayFn)> {
+    match day {
+        18 => {
+            use day18::{star_one_flat, star_two_flat};
+            vec![("flat", star_one_flat as DayFn, star_two_flat as DayFn)]
+        }
+        22 => {
+            use day22::{star_one, star_two_cuboids, star_two_signed};
+            vec![
+                ("signed", star_one as DayFn, star_two_signed as DayFn),
+                ("cuboids", star_one as DayFn, star_two_cuboids as DayFn),
+            ]
+        }
+        _ => Vec::new(),
+    }
+}
+
+// Day 19's scanner alignment as JSON, PLY or OBJ.
+pub use day19::export as export_scanners;
+
 pub fn get_days() -> impl Iterator<Item = usize> {
     1..=21
 }
@@ -275,6 +298,31 @@ pub fn get_data(filepath: &Path) -> Box<dyn BufRead> {
     Box::new(input)
 }
 
+// Steps a 64-bit linear congruential generator and returns the top 31 bits
+// of the new state, which are the only reasonably random ones.
+fn lcg(state: &mut u64) -> u64 {
+    *state = state
+        .wrapping_mul(6364136223846793005)
+        .wrapping_add(1442695040888963407);
+    *state >> 33
+}
+
+// Reproducible random numbers for generated test inputs.
+#[cfg(test)]
+struct Random(u64);
+
+#[cfg(test)]
+impl Random {
+    fn next(&mut self) -> u64 {
+        lcg(&mut self.0)
+    }
+
+    // A number in -range..=range.
+    fn range(&mut self, range: isize) -> isize {
+        (self.next() as isize).rem_euclid(2 * range + 1) - range
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -421,6 +469,11 @@ mod tests {
         assert_eq!(star_one(get_data(&filepath)), 4289);
 
         assert_eq!(star_two(get_data(&filepath)), 4807);
+
+        for (_name, star_one, star_two) in get_variants(18) {
+            assert_eq!(star_one(get_data(&filepath)), 4289);
+            assert_eq!(star_two(get_data(&filepath)), 4807);
+        }
     }
 
     #[test]
@@ -0,0 +1,65 @@
+use num_bigint::BigUint;
+
+// Square matrices of arbitrarily large counts, for stepping linear
+// recurrences many steps at once.
+pub type Matrix = Vec<Vec<BigUint>>;
+
+pub fn identity(n: usize) -> Matrix {
+    (0..n)
+        .map(|i| (0..n).map(|j| BigUint::from((i == j) as u8)).collect())
+        .collect()
+}
+
+pub fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let n = a.len();
+    let zero = BigUint::default();
+    let mut result = vec![vec![BigUint::default(); n]; n];
+    for i in 0..n {
+        for k in 0..n {
+            if a[i][k] == zero {
+                continue;
+            }
+            for j in 0..n {
+                if b[k][j] != zero {
+                    result[i][j] += &a[i][k] * &b[k][j];
+                }
+            }
+        }
+    }
+    result
+}
+
+pub fn power(matrix: &Matrix, mut exponent: usize) -> Matrix {
+    let mut result = identity(matrix.len());
+    let mut base = matrix.clone();
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = multiply(&result, &base);
+        }
+        exponent >>= 1;
+        if exponent > 0 {
+            base = multiply(&base, &base);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_power() {
+        // Fibonacci numbers.
+        let matrix: Matrix = vec![
+            vec![BigUint::from(1u8), BigUint::from(1u8)],
+            vec![BigUint::from(1u8), BigUint::from(0u8)],
+        ];
+        assert_eq!(power(&matrix, 0), identity(2));
+        assert_eq!(power(&matrix, 10)[0][1], BigUint::from(55u8));
+        assert_eq!(
+            power(&matrix, 100)[0][1].to_string(),
+            "354224848179261915075"
+        );
+    }
+}